@@ -0,0 +1,290 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::TcpListener;
+
+use crate::http::{self, HttpRequest, HttpResponse};
+use crate::message::DEFAULT_RECENT_CAPACITY;
+use crate::SharedHandler;
+
+/// 默认返回的最近消息条数
+const DEFAULT_MESSAGE_LIMIT: usize = 50;
+
+/// POST /api/messages 的请求体
+#[derive(Deserialize)]
+struct SendRequest {
+    target: String,
+    message: String,
+}
+
+/// POST /api/receiver/start 的请求体
+#[derive(Deserialize)]
+struct StartRequest {
+    port: u16,
+}
+
+/// 在后台线程中启动本地 HTTP API
+///
+/// 每个请求都要带上 `Authorization: Bearer <token>`，POST 请求的 Content-Type 必须是
+/// `application/json`，使浏览器中的其他网页无法跨站调用接口。
+///
+/// 接口列表:
+/// - `GET  /api/status`            处理器状态和流量统计
+/// - `GET  /api/messages?limit=N`  最近接收的消息
+/// - `POST /api/messages`          发送消息 `{"target": "IP:端口", "message": "..."}`
+/// - `POST /api/receiver/start`    启动接收器 `{"port": 8080}`
+/// - `POST /api/receiver/stop`     停止接收器
+/// - `GET  /api/frp`               frp 状态
+/// - `POST /api/frp/init|start|stop`
+pub fn spawn_api_server(
+    addr: SocketAddr,
+    token: String,
+    handler: SharedHandler,
+    status_sender: Sender<String>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?;
+
+    let handle = thread::spawn(move || {
+        runtime.block_on(async move {
+            let listener = match TcpListener::from_std(listener) {
+                Ok(l) => l,
+                Err(e) => {
+                    let _ = status_sender.send(format!("HTTP API 启动失败: {}", e));
+                    return;
+                }
+            };
+            let _ = status_sender.send(format!("HTTP API 已启动: http://{}/api/status", addr));
+            if !addr.ip().is_loopback() {
+                let _ = status_sender.send(format!(
+                    "警告: HTTP API 绑定在 {}，其他主机也可以访问，建议只绑定 127.0.0.1",
+                    addr.ip()
+                ));
+            }
+
+            loop {
+                let (mut stream, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        let _ = status_sender.send(format!("HTTP API 接受连接失败: {}", e));
+                        continue;
+                    }
+                };
+                let handler = handler.clone();
+                let status_sender = status_sender.clone();
+                let token = token.clone();
+                tokio::spawn(async move {
+                    let request = match http::read_request(&mut stream).await {
+                        Ok(req) => req,
+                        Err(e) => {
+                            let response = error(http::error_status(&e), &format!("无效的请求: {}", e));
+                            let _ = response.write_to(&mut stream).await;
+                            return;
+                        }
+                    };
+                    if let Err(response) = check_request(&request, &token) {
+                        let _ = response.write_to(&mut stream).await;
+                        return;
+                    }
+                    // 处理器操作可能阻塞（例如停止 frp 进程），放到阻塞线程池中执行
+                    let response = tokio::task::spawn_blocking(move || {
+                        route(&handler, &request, &status_sender)
+                    })
+                    .await
+                    .unwrap_or_else(|e| error(500, &format!("内部错误: {}", e)));
+                    let _ = response.write_to(&mut stream).await;
+                });
+            }
+        });
+    });
+
+    Ok(handle)
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    HttpResponse::json(status, &json!({ "error": message }))
+}
+
+/// 检查访问令牌和 POST 请求的 Content-Type
+fn check_request(request: &HttpRequest, token: &str) -> Result<(), HttpResponse> {
+    let given = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !http::token_matches(token, given.trim()) {
+        return Err(error(401, "缺少或错误的访问令牌"));
+    }
+    if request.method == "POST" {
+        let content_type = request.header("content-type").unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if !mime.eq_ignore_ascii_case("application/json") {
+            return Err(error(415, "请求的 Content-Type 必须是 application/json"));
+        }
+    }
+    Ok(())
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(request: &'a HttpRequest) -> Result<T, HttpResponse> {
+    serde_json::from_slice(&request.body).map_err(|e| error(400, &format!("无效的 JSON 请求体: {}", e)))
+}
+
+/// 分发请求
+fn route(handler: &SharedHandler, request: &HttpRequest, status_sender: &Sender<String>) -> HttpResponse {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/status") => status(handler),
+        ("GET", "/api/messages") => list_messages(handler, request),
+        ("POST", "/api/messages") => send_message(handler, request),
+        ("POST", "/api/receiver/start") => start_receiver(handler, request, status_sender),
+        ("POST", "/api/receiver/stop") => stop_receiver(handler),
+        ("GET", "/api/frp") => frp_status(handler),
+        ("POST", "/api/frp/init") => frp_action(handler, |h| h.init_frp(None)),
+        ("POST", "/api/frp/start") => frp_action(handler, |h| h.start_frp()),
+        ("POST", "/api/frp/stop") => frp_action(handler, |h| h.stop_frp()),
+        (_, "/api/status" | "/api/messages" | "/api/receiver/start" | "/api/receiver/stop"
+            | "/api/frp" | "/api/frp/init" | "/api/frp/start" | "/api/frp/stop") => {
+            error(405, "不支持的请求方法")
+        }
+        _ => HttpResponse::not_found(),
+    }
+}
+
+fn status(handler: &SharedHandler) -> HttpResponse {
+    let handler = handler.lock().unwrap();
+    let stats = handler.stats();
+    let peers: Vec<_> = stats
+        .peers
+        .iter()
+        .map(|(addr, peer)| {
+            json!({
+                "peer": addr.to_string(),
+                "packets_sent": peer.packets_sent,
+                "bytes_sent": peer.bytes_sent,
+                "packets_received": peer.packets_received,
+                "bytes_received": peer.bytes_received,
+            })
+        })
+        .collect();
+
+    HttpResponse::json(200, &json!({
+        "send_port": handler.local_send_port().ok(),
+        "receiving": handler.is_receiving(),
+        "receive_port": handler.receive_port(),
+        "output_file": handler.output_file().display().to_string(),
+        "stats": {
+            "started_at": stats.started_at.to_rfc3339(),
+            "packets_sent": stats.packets_sent,
+            "bytes_sent": stats.bytes_sent,
+            "packets_received": stats.packets_received,
+            "bytes_received": stats.bytes_received,
+            "decode_failures": stats.decode_failures,
+            "write_errors": stats.write_errors,
+            "receive_errors": stats.receive_errors,
+            "subscriber_drops": stats.subscriber_drops,
+            "peers": peers,
+            "other_peers": {
+                "evicted": stats.other_peers.evicted,
+                "packets_sent": stats.other_peers.stats.packets_sent,
+                "bytes_sent": stats.other_peers.stats.bytes_sent,
+                "packets_received": stats.other_peers.stats.packets_received,
+                "bytes_received": stats.other_peers.stats.bytes_received,
+            },
+        },
+        "frp_running": handler.is_frp_running(),
+    }))
+}
+
+fn list_messages(handler: &SharedHandler, request: &HttpRequest) -> HttpResponse {
+    let limit = match request.query.get("limit") {
+        Some(v) => match v.parse::<usize>() {
+            Ok(n) => n.min(DEFAULT_RECENT_CAPACITY),
+            Err(_) => return error(400, &format!("无效的 limit: {}", v)),
+        },
+        None => DEFAULT_MESSAGE_LIMIT,
+    };
+    let messages: Vec<_> = handler
+        .lock()
+        .unwrap()
+        .recent_messages(limit)
+        .iter()
+        .map(|m| m.to_json())
+        .collect();
+    HttpResponse::json(200, &json!({ "messages": messages }))
+}
+
+fn send_message(handler: &SharedHandler, request: &HttpRequest) -> HttpResponse {
+    let body: SendRequest = match parse_body(request) {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    match handler.lock().unwrap().send_message(&body.target, &body.message) {
+        Ok(size) => HttpResponse::json(200, &json!({ "target": body.target, "bytes": size })),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => error(400, &e.to_string()),
+        Err(e) => error(500, &format!("发送失败: {}", e)),
+    }
+}
+
+fn start_receiver(handler: &SharedHandler, request: &HttpRequest, status_sender: &Sender<String>) -> HttpResponse {
+    let body: StartRequest = match parse_body(request) {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+
+    // 接收器的状态信息转发到 API 的状态通道
+    let (status_tx, status_rx) = channel::<String>();
+    let forward = status_sender.clone();
+    let result = handler.lock().unwrap().start_receiver(body.port, status_tx);
+    match result {
+        Ok(()) => {
+            thread::spawn(move || {
+                for msg in status_rx {
+                    let _ = forward.send(msg);
+                }
+            });
+            HttpResponse::json(200, &json!({ "receiving": true, "receive_port": body.port }))
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => error(409, &e.to_string()),
+        Err(e) => error(500, &format!("启动接收器失败: {}", e)),
+    }
+}
+
+fn stop_receiver(handler: &SharedHandler) -> HttpResponse {
+    let mut handler = handler.lock().unwrap();
+    if !handler.is_receiving() {
+        return error(409, "接收器未运行");
+    }
+    handler.stop_receiver();
+    HttpResponse::json(200, &json!({ "receiving": false }))
+}
+
+fn frp_status(handler: &SharedHandler) -> HttpResponse {
+    let handler = handler.lock().unwrap();
+    match handler.get_frp_status() {
+        Some(status) => HttpResponse::json(200, &json!({
+            "initialized": true,
+            "running": handler.is_frp_running(),
+            "status": status,
+        })),
+        None => HttpResponse::json(200, &json!({ "initialized": false, "running": false })),
+    }
+}
+
+fn frp_action<F>(handler: &SharedHandler, action: F) -> HttpResponse
+where
+    F: FnOnce(&mut crate::UdpMessageHandler) -> anyhow::Result<()>,
+{
+    let mut handler = handler.lock().unwrap();
+    match action(&mut handler) {
+        Ok(()) => {
+            let running = handler.is_frp_running();
+            HttpResponse::json(200, &json!({ "running": running }))
+        }
+        Err(e) => error(500, &e.to_string()),
+    }
+}
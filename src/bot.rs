@@ -0,0 +1,97 @@
+use crate::message::ReceivedMessage;
+
+/// 内置机器人回复的前缀，带此前缀的消息不会再被内置机器人回复，
+/// 避免两个开启机器人的 NChat 实例互相无限回复
+pub const BOT_REPLY_PREFIX: &str = "[bot] ";
+
+/// 消息钩子
+///
+/// 接收线程对每条解析后的消息依次调用已注册的钩子，
+/// 返回 `Some(回复)` 时回复会自动发回消息来源地址。
+/// 钩子在接收线程中同步执行，应尽快返回。
+pub trait MessageHook: Send {
+    fn on_message(&mut self, message: &ReceivedMessage) -> Option<String>;
+}
+
+impl<F> MessageHook for F
+where
+    F: FnMut(&ReceivedMessage) -> Option<String> + Send,
+{
+    fn on_message(&mut self, message: &ReceivedMessage) -> Option<String> {
+        self(message)
+    }
+}
+
+/// 是否应由内置机器人处理该消息
+fn should_reply(message: &ReceivedMessage) -> bool {
+    !message.binary && !message.text.starts_with(BOT_REPLY_PREFIX)
+}
+
+/// 回显机器人：原样回复收到的消息
+#[derive(Debug, Default)]
+pub struct EchoBot;
+
+impl MessageHook for EchoBot {
+    fn on_message(&mut self, message: &ReceivedMessage) -> Option<String> {
+        should_reply(message).then(|| format!("{}{}", BOT_REPLY_PREFIX, message.text))
+    }
+}
+
+/// 自动回复机器人：对每条消息回复固定内容
+#[derive(Debug)]
+pub struct AutoReplyBot {
+    reply: String,
+}
+
+impl AutoReplyBot {
+    pub fn new(reply: &str) -> Self {
+        Self {
+            reply: reply.to_string(),
+        }
+    }
+}
+
+impl MessageHook for AutoReplyBot {
+    fn on_message(&mut self, message: &ReceivedMessage) -> Option<String> {
+        should_reply(message).then(|| format!("{}{}", BOT_REPLY_PREFIX, self.reply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn message(text: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            timestamp: Local::now(),
+            source: "127.0.0.1:9000".parse().unwrap(),
+            text: text.to_string(),
+            size: text.len(),
+            binary: false,
+        }
+    }
+
+    #[test]
+    fn echo_bot_prefixes_replies_and_ignores_bot_messages() {
+        let mut bot = EchoBot;
+        let reply = bot.on_message(&message("hello")).unwrap();
+        assert_eq!(reply, "[bot] hello");
+
+        // 对端机器人的回复带有前缀，不再回复，两端不会互相回显
+        assert_eq!(bot.on_message(&message(&reply)), None);
+        let binary = ReceivedMessage { binary: true, ..message("<BINARY DATA: 4 bytes>") };
+        assert_eq!(bot.on_message(&binary), None);
+    }
+
+    #[test]
+    fn auto_reply_bot_prefixes_replies_and_ignores_bot_messages() {
+        let mut bot = AutoReplyBot::new("稍后回复");
+        let reply = bot.on_message(&message("在吗")).unwrap();
+        assert_eq!(reply, "[bot] 稍后回复");
+        assert!(reply.starts_with(BOT_REPLY_PREFIX));
+
+        assert_eq!(bot.on_message(&message(&reply)), None);
+        assert_eq!(bot.on_message(&message("[bot] 其他机器人的回复")), None);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::frp::{FrpConfig, FrpPaths};
+use crate::LogFormat;
+
+/// 配置文件名
+pub const CONFIG_FILE_NAME: &str = "nchat.toml";
+
+/// Webhook 设置
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookSettings {
+    pub url: String,
+    #[serde(default)]
+    pub peers: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// 脚本钩子设置
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecSettings {
+    pub command: String,
+    pub concurrency: Option<usize>,
+    /// 超时时间（秒）
+    pub timeout: Option<u64>,
+}
+
+/// 启动时自动开启的功能
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutostartSettings {
+    /// 在 `receive_port` 上开启接收器
+    #[serde(default)]
+    pub receiver: bool,
+    /// 初始化并启动 frp，本地端口为 `receive_port`
+    #[serde(default)]
+    pub frp: bool,
+}
+
+/// 一组 NChat 设置
+///
+/// 配置文件顶层的设置对所有 profile 生效，`[profiles.<名称>]` 中的设置覆盖顶层设置。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub receive_port: Option<u16>,
+    pub autostart: Option<AutostartSettings>,
+    pub bind_address: Option<IpAddr>,
+    pub log_path: Option<String>,
+    pub log_format: Option<LogFormat>,
+    pub nickname: Option<String>,
+    /// 联系人名称到 `IP:端口` 的映射，发送消息时可以直接使用名称
+    #[serde(default)]
+    pub contacts: HashMap<String, String>,
+    /// 守护进程控制套接字路径
+    pub socket: Option<PathBuf>,
+    pub metrics: Option<SocketAddr>,
+    pub api: Option<SocketAddr>,
+    /// HTTP API 和 WebSocket 桥接的访问令牌，未设置时每次启动随机生成
+    pub api_token: Option<String>,
+    pub ws: Option<SocketAddr>,
+    /// 允许连接 WebSocket 桥接的网页来源，例如 `http://localhost:3000`
+    #[serde(default)]
+    pub ws_origins: Vec<String>,
+    pub webhook: Option<WebhookSettings>,
+    pub exec: Option<ExecSettings>,
+    pub frp: Option<FrpConfig>,
+    /// frp 配置目录、日志文件和 frpc 查找位置
+    pub frp_paths: Option<FrpPaths>,
+}
+
+impl Settings {
+    /// 用 `other` 中已设置的项覆盖当前设置（联系人合并）
+    fn merge(mut self, other: Settings) -> Settings {
+        self.contacts.extend(other.contacts);
+        Settings {
+            receive_port: other.receive_port.or(self.receive_port),
+            autostart: other.autostart.or(self.autostart),
+            bind_address: other.bind_address.or(self.bind_address),
+            log_path: other.log_path.or(self.log_path),
+            log_format: other.log_format.or(self.log_format),
+            nickname: other.nickname.or(self.nickname),
+            contacts: self.contacts,
+            socket: other.socket.or(self.socket),
+            metrics: other.metrics.or(self.metrics),
+            api: other.api.or(self.api),
+            api_token: other.api_token.or(self.api_token),
+            ws: other.ws.or(self.ws),
+            ws_origins: if other.ws_origins.is_empty() { self.ws_origins } else { other.ws_origins },
+            webhook: other.webhook.or(self.webhook),
+            exec: other.exec.or(self.exec),
+            frp: other.frp.or(self.frp),
+            frp_paths: other.frp_paths.or(self.frp_paths),
+        }
+    }
+}
+
+/// 配置文件
+#[derive(Debug, Default, Deserialize)]
+pub struct NChatConfig {
+    /// 未指定 `--profile` 时使用的 profile
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Settings>,
+    #[serde(flatten)]
+    pub base: Settings,
+    /// `base` 展开后 `deny_unknown_fields` 不再生效，无法识别的顶层设置收集到这里，由 `parse` 报错
+    #[serde(flatten)]
+    unknown: HashMap<String, toml::Value>,
+}
+
+impl NChatConfig {
+    /// 从文件读取配置
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("读取配置文件 {} 失败", path.display()))?;
+        Self::parse(&content)
+            .with_context(|| format!("解析配置文件 {} 失败", path.display()))
+    }
+
+    /// 解析配置文件内容，有无法识别的设置时返回错误
+    pub fn parse(content: &str) -> Result<Self> {
+        let config: NChatConfig = toml::from_str(content)?;
+        if !config.unknown.is_empty() {
+            let mut keys: Vec<&str> = config.unknown.keys().map(|k| k.as_str()).collect();
+            keys.sort();
+            return Err(anyhow::anyhow!("无法识别的设置: {}", keys.join(", ")));
+        }
+        Ok(config)
+    }
+
+    /// 按名称选出 profile 并与顶层设置合并
+    ///
+    /// 未指定名称时使用 `default_profile`，两者都没有时只使用顶层设置。
+    pub fn resolve(&self, profile: Option<&str>) -> Result<Settings> {
+        let name = profile.or(self.default_profile.as_deref());
+        let base = self.base.clone();
+        match name {
+            Some(name) => {
+                let selected = self.profiles.get(name).with_context(|| {
+                    let mut names: Vec<_> = self.profiles.keys().map(|k| k.as_str()).collect();
+                    names.sort();
+                    format!("配置文件中没有名为 '{}' 的 profile (可用: {})", name, names.join(", "))
+                })?;
+                Ok(base.merge(selected.clone()))
+            }
+            None => Ok(base),
+        }
+    }
+}
+
+/// 标准配置文件位置：当前目录下的 nchat.toml，
+/// 然后是用户配置目录下的 nchat/nchat.toml
+pub fn default_config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(CONFIG_FILE_NAME)];
+    if let Some(dir) = user_config_dir() {
+        paths.push(dir.join("nchat").join(CONFIG_FILE_NAME));
+    }
+    paths
+}
+
+fn user_config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }
+}
+
+/// 加载设置
+///
+/// 指定了 `path` 时该文件必须存在；否则依次查找标准位置，都不存在时使用空设置。
+/// 返回设置和实际使用的配置文件路径。
+pub fn load_settings(path: Option<&Path>, profile: Option<&str>) -> Result<(Settings, Option<PathBuf>)> {
+    let path = match path {
+        Some(p) => Some(p.to_path_buf()),
+        None => default_config_paths().into_iter().find(|p| p.is_file()),
+    };
+
+    match path {
+        Some(path) => {
+            let settings = NChatConfig::load(&path)?.resolve(profile)?;
+            Ok((settings, Some(path)))
+        }
+        None if profile.is_some() => Err(anyhow::anyhow!("指定了 profile，但没有找到配置文件")),
+        None => Ok((Settings::default(), None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unknown_top_level_settings() {
+        let err = NChatConfig::parse("recieve_port = 8080\nnickname = \"alice\"\nfoo = 1\n").unwrap_err();
+        assert_eq!(err.to_string(), "无法识别的设置: foo, recieve_port");
+
+        let err = NChatConfig::parse("[profiles.home]\nrecieve_port = 8080\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("recieve_port"));
+    }
+
+    #[test]
+    fn merges_profile_over_top_level_settings() {
+        let config = NChatConfig::parse(
+            "default_profile = \"home\"\nreceive_port = 8080\nnickname = \"alice\"\n\n[contacts]\nbob = \"10.0.0.2:8080\"\n\n[profiles.home]\nreceive_port = 9090\n\n[profiles.home.contacts]\ncarol = \"10.0.0.3:8080\"\n",
+        )
+        .unwrap();
+
+        let home = config.resolve(None).unwrap();
+        assert_eq!(home.receive_port, Some(9090));
+        assert_eq!(home.nickname.as_deref(), Some("alice"));
+        assert_eq!(home.contacts.len(), 2);
+
+        assert!(config.resolve(Some("work")).is_err());
+    }
+}
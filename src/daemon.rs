@@ -0,0 +1,442 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+use chrono::Local;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use crate::frp::{ProxyConfig, VisitorConfig, FRP_OPTIONS};
+use crate::{SharedHandler, UdpMessageHandler};
+
+/// 控制连接的读写超时
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 控制套接字文件名
+const SOCKET_FILE: &str = "nchat.sock";
+
+/// 默认控制套接字路径
+///
+/// 优先使用 `$XDG_RUNTIME_DIR/nchat.sock`；未设置时使用系统临时目录下当前用户的私有目录
+/// `nchat-<uid>/nchat.sock`，避免其他用户抢先创建同名套接字。
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(SOCKET_FILE),
+        None => private_temp_dir().join(SOCKET_FILE),
+    }
+}
+
+/// 系统临时目录下当前用户的私有目录
+fn private_temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("nchat-{}", current_uid()))
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid 没有参数，总是成功
+    unsafe { libc::getuid() }
+}
+
+/// 检查临时目录下的私有目录：必须是属于当前用户、其他用户无法访问的目录（不能是符号链接），
+/// 否则可能是其他用户抢先创建的
+fn check_private_dir(dir: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != current_uid() || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "控制套接字目录 {} 不属于当前用户或其他用户可以访问，请检查后删除，或用 --socket 指定路径",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// 准备控制套接字所在的目录，不存在时创建为只有当前用户可以访问 (0700)
+fn prepare_socket_dir(socket_path: &Path) -> io::Result<()> {
+    let Some(dir) = socket_path.parent().filter(|dir| !dir.as_os_str().is_empty()) else {
+        return Ok(());
+    };
+    if !dir.exists() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    if dir == private_temp_dir() {
+        check_private_dir(dir)?;
+    }
+    Ok(())
+}
+
+/// 守护进程启动选项
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub socket_path: PathBuf,
+    pub receive_port: Option<u16>,
+    pub start_frp: bool,
+}
+
+/// 执行一条控制命令，返回要回复给客户端的文本
+///
+/// 支持的命令:
+/// - `status`
+/// - `stats`
+/// - `send <IP:端口> <消息>`
+/// - `start <端口>` / `stop`
+/// - `frp init|start|stop|status|version`
+/// - `frp config <服务器地址> <端口> [token|env:变量名] [--type udp|sudp]`
+/// - `frp load [--raw] <frpc.toml 路径>`
+/// - `frp proxy list|add|rm ...`
+/// - `frp visitor list|add|rm|share ...`
+/// - `frp set <选项> <值|default>`
+pub fn execute_command(handler: &SharedHandler, line: &str) -> Result<String, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    // 查询代理实时状态时不能持有锁
+    if parts.starts_with(&["frp", "status"]) {
+        return Ok(UdpMessageHandler::frp_status_report(handler));
+    }
+    let mut handler = handler.lock().unwrap();
+
+    match parts.first().copied().unwrap_or("") {
+        "status" => Ok(handler.status_report()),
+        "stats" => {
+            let stats = handler.stats();
+            Ok(format!(
+                "已发送: {} 个报文 / {} 字节\n已接收: {} 个报文 / {} 字节\n错误: 解码失败 {} / 写入错误 {} / 接收错误 {} / 推送丢弃 {}\n",
+                stats.packets_sent, stats.bytes_sent,
+                stats.packets_received, stats.bytes_received,
+                stats.decode_failures, stats.write_errors, stats.receive_errors, stats.subscriber_drops
+            ))
+        }
+        "send" => {
+            let target = parts.get(1).ok_or("用法: send <IP:端口> <消息>")?;
+            // 保留消息中的原始空白
+            let message = line
+                .trim_start()
+                .strip_prefix("send")
+                .map(|rest| rest.trim_start())
+                .and_then(|rest| rest.strip_prefix(*target))
+                .map(|rest| rest.trim_start())
+                .unwrap_or("");
+            if message.is_empty() {
+                return Err("用法: send <IP:端口> <消息>".to_string());
+            }
+            handler
+                .send_message(target, message)
+                .map(|size| format!("成功发送 {} 字节到 {}\n", size, target))
+                .map_err(|e| format!("发送失败: {}", e))
+        }
+        "start" => {
+            let port = parts
+                .get(1)
+                .and_then(|p| p.parse::<u16>().ok())
+                .ok_or("用法: start <端口>")?;
+            start_receiver_with_log(&mut handler, port)
+                .map(|_| format!("接收器已启动，监听端口 {}\n", port))
+                .map_err(|e| format!("启动接收器失败: {}", e))
+        }
+        "stop" => {
+            if !handler.is_receiving() {
+                return Err("接收器未运行".to_string());
+            }
+            handler.stop_receiver();
+            Ok("接收器已停止\n".to_string())
+        }
+        "frp" => execute_frp_command(&mut handler, &parts[1..]),
+        "" => Err("空命令".to_string()),
+        other => Err(format!("未知命令 '{}'", other)),
+    }
+}
+
+/// 执行 frp 控制命令
+fn execute_frp_command(handler: &mut UdpMessageHandler, args: &[&str]) -> Result<String, String> {
+    match args.first().copied().unwrap_or("") {
+        "init" => handler
+            .init_frp(None)
+            .map(|_| "Frp 管理器已初始化\n".to_string())
+            .map_err(|e| format!("初始化 frp 失败: {}", e)),
+        "start" => handler
+            .start_frp()
+            .map(|_| "Frp 内网穿透已启动\n".to_string())
+            .map_err(|e| format!("启动 frp 失败: {}", e)),
+        "stop" => handler
+            .stop_frp()
+            .map(|_| "Frp 内网穿透已停止\n".to_string())
+            .map_err(|e| format!("停止 frp 失败: {}", e)),
+        "version" => Ok(handler.frp_version_report()),
+        "config" => {
+            let (args, protocol) = crate::split_type_option(&args[1..])?;
+            if args.len() < 2 {
+                return Err(format!("用法: {}", crate::FRP_CONFIG_USAGE));
+            }
+            let server_port = args[1]
+                .parse::<u16>()
+                .map_err(|_| format!("无效的端口号: {}", args[1]))?;
+            handler
+                .configure_frp(args[0], server_port, args.get(2).copied(), protocol)
+                .map(|_| "Frp 配置已更新\n".to_string())
+                .map_err(|e| format!("配置 frp 失败: {}", e))
+        }
+        "proxy" => match args.get(1).copied().unwrap_or("list") {
+            "list" => {
+                let mut out = String::new();
+                crate::write_proxy_list(&mut out, &handler.frp_proxies());
+                Ok(out)
+            }
+            "add" => {
+                let proxy = ProxyConfig::from_args(&args[2..]).map_err(|e| format!("{:#}", e))?;
+                let name = proxy.name.clone();
+                handler
+                    .add_frp_proxy(proxy)
+                    .map(|_| format!("已添加代理 {}\n", name))
+                    .map_err(|e| format!("添加代理失败: {:#}", e))
+            }
+            "rm" => {
+                let name = args.get(2).ok_or("用法: frp proxy rm <名称>")?;
+                handler
+                    .remove_frp_proxy(name)
+                    .map(|_| format!("已删除代理 {}\n", name))
+                    .map_err(|e| format!("删除代理失败: {:#}", e))
+            }
+            other => Err(format!("未知的 frp proxy 命令: {}", other)),
+        },
+        "load" => {
+            let (verbatim, path) = match args.get(1..) {
+                Some(["--raw", path]) => (true, *path),
+                Some([path]) => (false, *path),
+                _ => return Err("用法: frp load [--raw] <frpc.toml 路径>".to_string()),
+            };
+            handler
+                .load_frp_config(Path::new(path), verbatim)
+                .map(|_| format!("已加载 frp 配置: {}\n", path))
+                .map_err(|e| format!("加载 frp 配置失败: {:#}", e))
+        }
+        "visitor" => match args.get(1).copied().unwrap_or("list") {
+            "list" => {
+                let mut out = String::new();
+                crate::write_visitor_list(&mut out, &handler.frp_visitors());
+                Ok(out)
+            }
+            "add" => {
+                let visitor = VisitorConfig::from_args(&args[2..]).map_err(|e| format!("{:#}", e))?;
+                let name = visitor.name.clone();
+                handler
+                    .add_frp_visitor(visitor)
+                    .map(|_| format!("已添加 visitor {}\n", name))
+                    .map_err(|e| format!("添加 visitor 失败: {:#}", e))
+            }
+            "rm" => {
+                let name = args.get(2).ok_or("用法: frp visitor rm <名称>")?;
+                handler
+                    .remove_frp_visitor(name)
+                    .map(|_| format!("已删除 visitor {}\n", name))
+                    .map_err(|e| format!("删除 visitor 失败: {:#}", e))
+            }
+            "share" => {
+                let name = args.get(2).ok_or(format!("用法: {}", crate::FRP_SHARE_USAGE))?;
+                let bind_port = match args.get(3) {
+                    Some(port) => Some(port.parse::<u16>().map_err(|_| format!("无效的端口号: {}", port))?),
+                    None => None,
+                };
+                handler.frp_share_report(name, bind_port).map_err(|e| format!("{:#}", e))
+            }
+            other => Err(format!("未知的 frp visitor 命令: {}", other)),
+        },
+        "set" => match args.get(1..) {
+            Some([key, value]) => handler
+                .set_frp_option(key, value)
+                .map(|_| format!("已设置 {} = {}\n", key, value))
+                .map_err(|e| format!("设置 frp 选项失败: {:#}", e)),
+            _ => Err(format!("用法: {} (可用选项: {})", crate::FRP_SET_USAGE, FRP_OPTIONS.join(", "))),
+        },
+        other => Err(format!("未知的 frp 命令: {}", other)),
+    }
+}
+
+/// 启动接收器，并把状态信息带时间戳输出到标准输出
+fn start_receiver_with_log(handler: &mut UdpMessageHandler, port: u16) -> io::Result<()> {
+    let (status_tx, status_rx) = channel::<String>();
+    handler.start_receiver(port, status_tx)?;
+    thread::spawn(move || {
+        for msg in status_rx {
+            log(&msg);
+        }
+    });
+    Ok(())
+}
+
+fn log(msg: &str) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), msg);
+}
+
+/// 以守护进程模式运行，直到收到 `shutdown` 控制命令
+pub fn run_daemon(handler: SharedHandler, options: DaemonOptions) -> io::Result<()> {
+    let listener = bind_control_socket(&options.socket_path)?;
+    listener.set_nonblocking(true)?;
+    log(&format!("控制套接字: {}", options.socket_path.display()));
+
+    {
+        let (status_tx, status_rx) = channel::<String>();
+        handler.lock().unwrap().set_frp_status_sender(status_tx.clone());
+        let errors = handler.lock().unwrap().autostart(
+            options.receive_port,
+            options.receive_port.is_some(),
+            options.start_frp,
+            status_tx,
+        );
+        thread::spawn(move || {
+            for msg in status_rx {
+                log(&msg);
+            }
+        });
+        for e in errors {
+            log(&e);
+        }
+    }
+
+    let shutdown = AtomicBool::new(false);
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = serve_connection(stream, &handler, &shutdown) {
+                    log(&format!("控制连接错误: {}", e));
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => log(&format!("接受控制连接失败: {}", e)),
+        }
+    }
+
+    log("守护进程正在退出");
+    {
+        let mut guard = handler.lock().unwrap();
+        let _ = guard.stop_frp();
+        guard.stop_receiver();
+    }
+    let _ = fs::remove_file(&options.socket_path);
+    Ok(())
+}
+
+/// 绑定控制套接字，清理上次异常退出留下的套接字文件
+///
+/// 套接字文件权限设为 0600，只有当前用户可以连接。
+fn bind_control_socket(path: &Path) -> io::Result<UnixListener> {
+    prepare_socket_dir(path)?;
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("已有守护进程在运行: {}", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// 处理一个控制连接：读取一行命令，回复 `OK`/`ERR` 加正文
+fn serve_connection(stream: UnixStream, handler: &SharedHandler, shutdown: &AtomicBool) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CONTROL_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONTROL_TIMEOUT))?;
+
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line)? == 0 {
+        // 连接探测（例如检查守护进程是否存活），无需回复
+        return Ok(());
+    }
+    let line = line.trim();
+
+    let result = if line == "shutdown" {
+        shutdown.store(true, Ordering::SeqCst);
+        Ok("守护进程正在退出\n".to_string())
+    } else {
+        execute_command(handler, line)
+    };
+
+    let mut stream = &stream;
+    match result {
+        Ok(body) => write!(stream, "OK\n{}", body)?,
+        Err(msg) => writeln!(stream, "ERR\n{}", msg)?,
+    }
+    stream.flush()
+}
+
+/// 向正在运行的守护进程发送一条命令
+///
+/// 外层 `Err` 表示无法连接或通信失败，内层 `Err` 表示守护进程报告的命令错误。
+pub fn send_control_command(socket_path: &Path, command: &str) -> io::Result<Result<String, String>> {
+    if let Some(dir) = socket_path.parent().filter(|dir| *dir == private_temp_dir()) {
+        check_private_dir(dir)?;
+    }
+    let mut stream = UnixStream::connect(socket_path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("无法连接守护进程 {}: {}", socket_path.display(), e),
+        )
+    })?;
+    stream.set_read_timeout(Some(CONTROL_TIMEOUT * 2))?;
+    writeln!(stream, "{}", command.replace('\n', " "))?;
+    stream.flush()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.split_once('\n') {
+        Some(("OK", body)) => Ok(Ok(body.to_string())),
+        Some(("ERR", body)) => Ok(Err(body.trim_end().to_string())),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "守护进程返回了无效的响应")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nchat-daemon-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn binds_socket_in_private_dir_with_owner_only_permissions() {
+        let dir = temp_dir("bind");
+        let path = dir.join("run").join(SOCKET_FILE);
+        let listener = bind_control_socket(&path).unwrap();
+
+        let dir_mode = fs::metadata(path.parent().unwrap()).unwrap().mode();
+        assert_eq!(dir_mode & 0o777, 0o700);
+        let socket_mode = fs::metadata(&path).unwrap().mode();
+        assert_eq!(socket_mode & 0o777, 0o600);
+
+        // 已有守护进程在监听时拒绝绑定
+        assert_eq!(bind_control_socket(&path).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+        // 上次异常退出留下的套接字文件会被清理
+        bind_control_socket(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn private_dir_must_not_be_accessible_by_others() {
+        let dir = temp_dir("private");
+        fs::DirBuilder::new().mode(0o700).create(&dir).unwrap();
+        check_private_dir(&dir).unwrap();
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(check_private_dir(&dir).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let link = dir.with_extension("link");
+        let _ = fs::remove_file(&link);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(check_private_dir(&link).is_err());
+        let _ = fs::remove_file(&link);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
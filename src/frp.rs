@@ -1,78 +1,1816 @@
 use std::process::{Child, Command, Stdio};
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
-use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use anyhow::{Result, Context};
 
-/// Frp 配置结构
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::frp_admin::{AdminApi, ProxyState};
+use crate::frp_download;
+use crate::frp_log::{self, FrpConnection};
+
+/// frpc 退出后检查其输出时最多读取的字节数
+const MAX_EXIT_OUTPUT: u64 = 64 * 1024;
+/// 监控线程检查 frpc 进程的间隔
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(500);
+/// 首次自动重启前的等待时间，之后每次翻倍
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// 自动重启等待时间上限
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+/// 进程持续运行超过该时间后重置退避和失败计数
+const STABLE_RUN_TIME: Duration = Duration::from_secs(60);
+/// 连续自动重启次数上限
+const MAX_RESTART_ATTEMPTS: u32 = 10;
+
+/// 默认的 frp 配置目录
+pub const FRP_CONFIG_DIR: &str = "frp_config";
+/// 默认的 frpc 日志文件
+pub const FRP_LOG_FILE: &str = "frpc.log";
+/// 保存的 frp 设置文件名（不含 token）
+const SAVED_CONFIG_FILE: &str = "nchat-frp.toml";
+/// 单独保存 token 的文件名
+const TOKEN_FILE: &str = "token";
+/// 启动 frpc 时通过该环境变量传递 token，生成的 frpc.toml 中只引用该变量
+pub const TOKEN_ENV: &str = "NCHAT_FRP_TOKEN";
+/// 状态输出中代替 token 显示的掩码
+pub const TOKEN_MASK: &str = "******";
+
+/// frp 支持的代理类型
+pub const PROXY_TYPES: &[&str] = &["tcp", "udp", "http", "https", "stcp", "sudp", "xtcp"];
+/// NChat 通过 UDP 收发消息，自身代理默认使用 UDP
+pub const DEFAULT_PROXY_TYPE: &str = "udp";
+/// 需要 secret key、由对端通过 visitor 访问的代理类型
+pub const SECRET_PROXY_TYPES: &[&str] = &["stcp", "sudp", "xtcp"];
+/// visitor 支持的类型
+pub const VISITOR_TYPES: &[&str] = &["stcp", "sudp", "xtcp"];
+/// xtcp 代理和 visitor 对应的 stcp 回退项的名称后缀
+pub const STCP_FALLBACK_SUFFIX: &str = "-stcp";
+/// 自动生成的 secret key 长度
+const SECRET_KEY_LEN: usize = 24;
+/// visitor 默认绑定的本地地址
+pub const DEFAULT_VISITOR_BIND_ADDR: &str = "127.0.0.1";
+/// frpc 与服务器之间支持的传输协议
+pub const TRANSPORT_PROTOCOLS: &[&str] = &["tcp", "kcp", "quic", "websocket", "wss"];
+/// frpc 支持的日志级别
+pub const LOG_LEVELS: &[&str] = &["trace", "debug", "info", "warn", "error"];
+/// `FrpConfig::set_option` 支持的选项
+pub const FRP_OPTIONS: &[&str] = &[
+    "user",
+    "login_fail_exit",
+    "log_level",
+    "transport.protocol",
+    "transport.tls",
+    "transport.pool_count",
+    "transport.heartbeat_interval",
+    "transport.heartbeat_timeout",
+    "admin_port",
+];
+
+/// 检查代理类型是否为 frp 支持的类型
+pub fn validate_proxy_type(protocol: &str) -> Result<()> {
+    if PROXY_TYPES.contains(&protocol) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "不支持的代理类型: {} (可用: {})",
+            protocol,
+            PROXY_TYPES.join(", ")
+        ))
+    }
+}
+
+/// NChat 自身使用的代理名称，该代理的本地端口跟随接收端口
+pub const NCHAT_PROXY_NAME: &str = "nchat";
+/// NChat 自身代理可用的类型：消息通过 UDP 收发，只能使用转发 UDP 的代理
+pub const NCHAT_PROXY_TYPES: &[&str] = &["udp", "sudp"];
+
+/// 检查 NChat 自身代理的类型
+///
+/// tcp、stcp、xtcp、http、https 只转发 TCP 流量，NChat 的 UDP 消息无法通过。
+pub fn validate_nchat_proxy_type(protocol: &str) -> Result<()> {
+    validate_proxy_type(protocol)?;
+    if NCHAT_PROXY_TYPES.contains(&protocol) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "代理 {} 转发 NChat 的 UDP 消息，只能使用 {} 类型，{} 只转发 TCP 流量",
+            NCHAT_PROXY_NAME,
+            NCHAT_PROXY_TYPES.join("/"),
+            protocol
+        ))
+    }
+}
+
+/// Frp 配置结构：客户端设置加上代理列表
+///
+/// 从配置文件读取时，未填写的字段使用 `default_frp_config` 中的值。
+/// `Debug` 和序列化输出中 token 只显示为掩码，token 由 `save` 单独保存。
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct FrpConfig {
     pub server_addr: String,
     pub server_port: u16,
+    #[serde(serialize_with = "serialize_masked_token")]
     pub token: Option<String>,
+    /// 从该环境变量读取 token，设置后忽略 `token`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    pub proxies: Vec<ProxyConfig>,
+    /// 访问对端 stcp/sudp 代理的 visitor
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub visitors: Vec<VisitorConfig>,
+    /// 原样使用的 frpc 配置文件；设置后启动时不再生成 frpc.toml
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<PathBuf>,
+    /// 使用托管目录中该版本的 frpc，未设置时使用已安装的最新版本
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_version")]
+    pub version: Option<String>,
+    /// 登录服务器使用的用户名，服务器上的代理名称会加上 `用户名.` 前缀
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// 首次登录失败时 frpc 是否退出（frpc 默认退出）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_fail_exit: Option<bool>,
+    /// frpc 日志级别：trace、debug、info、warn 或 error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    #[serde(skip_serializing_if = "TransportConfig::is_default")]
+    pub transport: TransportConfig,
+    /// frpc 管理接口 (webServer) 的本机端口，未设置时每次启动选择空闲端口
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_port: Option<u16>,
+}
+
+/// frpc 与服务器之间的传输设置，未设置的项使用 frpc 的默认值
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportConfig {
+    /// 传输协议：tcp、kcp、quic、websocket 或 wss
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// 是否用 TLS 加密与服务器之间的连接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<bool>,
+    /// 预先建立的连接数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_count: Option<u32>,
+    /// 心跳间隔（秒），-1 表示关闭心跳
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_interval: Option<i64>,
+    /// 心跳超时（秒），-1 表示不检查超时
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat_timeout: Option<i64>,
+}
+
+/// 单个代理配置
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    pub name: String,
+    pub protocol: String,
     pub local_port: u16,
     pub remote_port: Option<u16>,
-    pub protocol: String,
+    /// 本地服务地址，未设置时为 127.0.0.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_ip: Option<String>,
+    /// 加密 frpc 与服务器之间传输的代理数据
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub use_encryption: bool,
+    /// 压缩 frpc 与服务器之间传输的代理数据
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub use_compression: bool,
+    /// stcp/sudp/xtcp 代理的 secret key，visitor 需要使用相同的 key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    /// 允许访问 stcp/sudp/xtcp 代理的其他用户，`*` 表示所有用户（默认只允许同一用户）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_users: Vec<String>,
+    /// xtcp 代理同时生成名为 `<名称>-stcp` 的 stcp 代理，P2P 打洞失败时对端经服务器中转
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub stcp_fallback: bool,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("name", &self.name)
+            .field("protocol", &self.protocol)
+            .field("local_port", &self.local_port)
+            .field("remote_port", &self.remote_port)
+            .field("local_ip", &self.local_ip)
+            .field("use_encryption", &self.use_encryption)
+            .field("use_compression", &self.use_compression)
+            .field("secret_key", &self.secret_key.as_ref().map(|_| TOKEN_MASK))
+            .field("allow_users", &self.allow_users)
+            .field("stcp_fallback", &self.stcp_fallback)
+            .finish()
+    }
+}
+
+/// 访问对端 stcp/sudp/xtcp 代理的 visitor：在本地监听 `bind_addr:bind_port`，
+/// 发往该地址的流量经 frp 服务器转发（xtcp 为 P2P 直连）到对端代理的本地服务
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct VisitorConfig {
     pub name: String,
+    /// visitor 类型，与对端代理类型相同
+    pub protocol: String,
+    /// 对端代理名称
+    pub server_name: String,
+    /// 对端代理所属的用户，对端未设置 `user` 时不需要
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_user: Option<String>,
+    pub secret_key: String,
+    /// 本地监听地址，未设置时为 127.0.0.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_addr: Option<String>,
+    pub bind_port: u16,
+    /// xtcp visitor 打洞失败时改用 `<名称>-stcp` visitor 连接对端的 `<对端代理名>-stcp` 代理
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub stcp_fallback: bool,
+    /// 等待打洞成功的时间（毫秒），超时后回退到 stcp，未设置时使用 frpc 默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_timeout_ms: Option<u32>,
+    /// 保持 xtcp 隧道，不等有连接时才打洞
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub keep_tunnel_open: bool,
+}
+
+impl fmt::Debug for VisitorConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VisitorConfig")
+            .field("name", &self.name)
+            .field("protocol", &self.protocol)
+            .field("server_name", &self.server_name)
+            .field("server_user", &self.server_user)
+            .field("secret_key", &TOKEN_MASK)
+            .field("bind_addr", &self.bind_addr)
+            .field("bind_port", &self.bind_port)
+            .field("stcp_fallback", &self.stcp_fallback)
+            .field("fallback_timeout_ms", &self.fallback_timeout_ms)
+            .field("keep_tunnel_open", &self.keep_tunnel_open)
+            .finish()
+    }
+}
+
+impl VisitorConfig {
+    /// 从命令参数 `<名称> <类型> <对端代理名> <secret key> <本地端口> [选项]` 解析
+    ///
+    /// xtcp visitor 默认回退到 stcp，`--no-fallback` 关闭回退。
+    pub fn from_args(args: &[&str]) -> Result<Self> {
+        let mut positional = Vec::new();
+        let mut server_user = None;
+        let mut bind_addr = None;
+        let mut no_fallback = false;
+        let mut fallback_timeout_ms = None;
+        let mut keep_tunnel_open = false;
+        let mut iter = args.iter();
+        while let Some(&arg) = iter.next() {
+            match arg {
+                "--server-user" => {
+                    let value = iter.next().context("--server-user 需要指定用户名")?;
+                    server_user = Some(value.to_string());
+                }
+                "--bind-addr" => {
+                    let value = iter.next().context("--bind-addr 需要指定地址")?;
+                    bind_addr = Some(value.to_string());
+                }
+                "--no-fallback" => no_fallback = true,
+                "--fallback-timeout" => {
+                    let value = iter.next().context("--fallback-timeout 需要指定毫秒数")?;
+                    fallback_timeout_ms = Some(parse_number(value)?);
+                }
+                "--keep-open" => keep_tunnel_open = true,
+                other if other.starts_with("--") => {
+                    return Err(anyhow::anyhow!("未知的选项: {}", other));
+                }
+                other => positional.push(other),
+            }
+        }
+
+        let (name, protocol, server_name, secret_key, bind_port) = match positional[..] {
+            [name, protocol, server_name, secret_key, bind_port] => {
+                (name, protocol, server_name, secret_key, bind_port)
+            }
+            _ => return Err(anyhow::anyhow!("用法: {}", VISITOR_ADD_USAGE)),
+        };
+        let bind_port = bind_port
+            .parse::<u16>()
+            .with_context(|| format!("无效的本地端口: {}", bind_port))?;
+        let visitor = VisitorConfig {
+            name: name.to_string(),
+            protocol: protocol.to_string(),
+            server_name: server_name.to_string(),
+            server_user,
+            secret_key: secret_key.to_string(),
+            bind_addr,
+            bind_port,
+            stcp_fallback: protocol == "xtcp" && !no_fallback,
+            fallback_timeout_ms,
+            keep_tunnel_open,
+        };
+        visitor.validate()?;
+        Ok(visitor)
+    }
+
+    /// 检查 visitor 配置
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("visitor 名称不能为空"));
+        }
+        if !VISITOR_TYPES.contains(&self.protocol.as_str()) {
+            return Err(anyhow::anyhow!(
+                "不支持的 visitor 类型: {} (可用: {})",
+                self.protocol,
+                VISITOR_TYPES.join(", ")
+            ));
+        }
+        if self.server_name.trim().is_empty() {
+            return Err(anyhow::anyhow!("对端代理名称不能为空"));
+        }
+        if self.secret_key.is_empty() {
+            return Err(anyhow::anyhow!("secret key 不能为空"));
+        }
+        if self.bind_port == 0 {
+            return Err(anyhow::anyhow!("本地端口不能为 0"));
+        }
+        if let Some(ref addr) = self.bind_addr {
+            if addr.parse::<IpAddr>().is_err() {
+                return Err(anyhow::anyhow!("无效的本地监听地址: {}", addr));
+            }
+        }
+        if self.protocol != "xtcp" {
+            if self.stcp_fallback || self.fallback_timeout_ms.is_some() || self.keep_tunnel_open {
+                return Err(anyhow::anyhow!("stcp 回退和隧道保持只适用于 xtcp visitor"));
+            }
+        } else if self.fallback_timeout_ms.is_some() && !self.stcp_fallback {
+            return Err(anyhow::anyhow!("未开启 stcp 回退时不能设置回退超时"));
+        }
+        if self.fallback_timeout_ms == Some(0) {
+            return Err(anyhow::anyhow!("回退超时必须大于 0"));
+        }
+        Ok(())
+    }
+
+    /// 本地监听地址
+    pub fn bind_addr(&self) -> &str {
+        self.bind_addr.as_deref().unwrap_or(DEFAULT_VISITOR_BIND_ADDR)
+    }
+
+    /// 开启 stcp 回退时，生成的 stcp visitor 名称
+    pub fn fallback_name(&self) -> Option<String> {
+        self.stcp_fallback.then(|| stcp_fallback_name(&self.name))
+    }
+}
+
+/// xtcp 代理或 visitor `name` 对应的 stcp 回退项名称
+pub fn stcp_fallback_name(name: &str) -> String {
+    format!("{}{}", name, STCP_FALLBACK_SUFFIX)
+}
+
+/// 访问本地端口为 `local_port` 的代理时，对端 visitor 默认监听的端口
+pub fn default_visitor_port(local_port: u16) -> u16 {
+    local_port.checked_add(1).unwrap_or(local_port - 1)
+}
+
+/// `frp visitor add` 命令用法
+pub const VISITOR_ADD_USAGE: &str =
+    "frp visitor add <名称> <类型> <对端代理名> <secret key> <本地端口> [--server-user 用户] [--bind-addr 地址] [--no-fallback] [--fallback-timeout 毫秒] [--keep-open]";
+
+/// 生成随机的 secret key
+pub fn generate_secret_key() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_KEY_LEN)
+        .map(char::from)
+        .collect()
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl TransportConfig {
+    fn is_default(&self) -> bool {
+        *self == TransportConfig::default()
+    }
+
+    /// 检查传输设置
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ref protocol) = self.protocol {
+            if !TRANSPORT_PROTOCOLS.contains(&protocol.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "不支持的传输协议: {} (可用: {})",
+                    protocol,
+                    TRANSPORT_PROTOCOLS.join(", ")
+                ));
+            }
+        }
+        if self.pool_count == Some(0) {
+            return Err(anyhow::anyhow!("连接池大小必须大于 0"));
+        }
+        for (name, value) in [("心跳间隔", self.heartbeat_interval), ("心跳超时", self.heartbeat_timeout)] {
+            if let Some(value) = value {
+                if value != -1 && value <= 0 {
+                    return Err(anyhow::anyhow!("{}必须大于 0 或为 -1: {}", name, value));
+                }
+            }
+        }
+        if let (Some(interval), Some(timeout)) = (self.heartbeat_interval, self.heartbeat_timeout) {
+            if interval > 0 && timeout > 0 && timeout <= interval {
+                return Err(anyhow::anyhow!("心跳超时 ({} 秒) 必须大于心跳间隔 ({} 秒)", timeout, interval));
+            }
+        }
+        Ok(())
+    }
+
+    /// 已设置的传输选项的描述，全部未设置时为空
+    pub fn describe(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        if let Some(ref protocol) = self.protocol {
+            items.push(format!("协议 {}", protocol));
+        }
+        if let Some(tls) = self.tls {
+            items.push(format!("TLS {}", if tls { "开启" } else { "关闭" }));
+        }
+        if let Some(count) = self.pool_count {
+            items.push(format!("连接池 {}", count));
+        }
+        if let Some(interval) = self.heartbeat_interval {
+            items.push(format!("心跳间隔 {} 秒", interval));
+        }
+        if let Some(timeout) = self.heartbeat_timeout {
+            items.push(format!("心跳超时 {} 秒", timeout));
+        }
+        items
+    }
+}
+
+impl fmt::Debug for FrpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrpConfig")
+            .field("server_addr", &self.server_addr)
+            .field("server_port", &self.server_port)
+            .field("token", &self.token.as_ref().map(|_| TOKEN_MASK))
+            .field("token_env", &self.token_env)
+            .field("proxies", &self.proxies)
+            .field("visitors", &self.visitors)
+            .field("config_file", &self.config_file)
+            .field("version", &self.version)
+            .field("user", &self.user)
+            .field("login_fail_exit", &self.login_fail_exit)
+            .field("log_level", &self.log_level)
+            .field("transport", &self.transport)
+            .field("admin_port", &self.admin_port)
+            .finish()
+    }
+}
+
+fn serialize_masked_token<S: Serializer>(token: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    token.as_ref().map(|_| TOKEN_MASK).serialize(serializer)
+}
+
+/// 读取配置时检查版本号，版本号会拼接进托管目录路径
+fn deserialize_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|version| frp_download::parse_version(&version).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// frpc TOML 配置文件中 NChat 关心的部分，其余字段忽略
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrpcFile {
+    server_addr: Option<String>,
+    server_port: Option<u16>,
+    user: Option<String>,
+    login_fail_exit: Option<bool>,
+    #[serde(default)]
+    auth: FrpcAuth,
+    #[serde(default)]
+    log: FrpcLog,
+    #[serde(default)]
+    transport: FrpcTransport,
+    #[serde(default)]
+    proxies: Vec<FrpcProxy>,
+    #[serde(default)]
+    visitors: Vec<FrpcVisitor>,
+}
+
+#[derive(Default, Deserialize)]
+struct FrpcAuth {
+    token: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+struct FrpcLog {
+    level: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrpcTransport {
+    protocol: Option<String>,
+    #[serde(default)]
+    tls: FrpcTls,
+    pool_count: Option<u32>,
+    heartbeat_interval: Option<i64>,
+    heartbeat_timeout: Option<i64>,
+}
+
+#[derive(Default, Deserialize)]
+struct FrpcTls {
+    enable: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrpcProxy {
+    name: String,
+    #[serde(rename = "type")]
+    proxy_type: String,
+    #[serde(rename = "localIP")]
+    local_ip: Option<String>,
+    local_port: Option<u16>,
+    remote_port: Option<u16>,
+    #[serde(default)]
+    transport: FrpcProxyTransport,
+    secret_key: Option<String>,
+    #[serde(default)]
+    allow_users: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrpcVisitor {
+    name: String,
+    #[serde(rename = "type")]
+    visitor_type: String,
+    server_name: String,
+    server_user: Option<String>,
+    secret_key: Option<String>,
+    bind_addr: Option<String>,
+    /// 作为回退的 stcp visitor 使用 -1，表示不在本地监听
+    bind_port: Option<i32>,
+    fallback_to: Option<String>,
+    fallback_timeout_ms: Option<u32>,
+    #[serde(default)]
+    keep_tunnel_open: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrpcProxyTransport {
+    #[serde(default)]
+    use_encryption: bool,
+    #[serde(default)]
+    use_compression: bool,
+}
+
+impl FrpConfig {
+    /// 解析已有的 frpc TOML 配置文件内容
+    ///
+    /// 未填写的字段使用默认值。`auth.token` 为 `{{ .Envs.变量名 }}` 形式时改为从该环境变量读取 token。
+    /// 缺少 `localPort` 的代理（例如 http 类型只写了 `localIP`）会被跳过并给出提示。
+    /// 按 NChat 生成格式配对的 xtcp 与 `-stcp` 代理/visitor 合并为开启 stcp 回退的一项。
+    pub fn from_frpc_toml(content: &str) -> Result<FrpConfig> {
+        let file: FrpcFile = toml::from_str(content).context("解析 frpc 配置失败")?;
+        let defaults = default_frp_config();
+
+        let mut proxies = Vec::new();
+        for proxy in file.proxies {
+            match proxy.local_port {
+                Some(local_port) => proxies.push(ProxyConfig {
+                    name: proxy.name,
+                    protocol: proxy.proxy_type,
+                    local_port,
+                    remote_port: proxy.remote_port,
+                    local_ip: proxy.local_ip,
+                    use_encryption: proxy.transport.use_encryption,
+                    use_compression: proxy.transport.use_compression,
+                    secret_key: proxy.secret_key,
+                    allow_users: proxy.allow_users,
+                    stcp_fallback: false,
+                }),
+                None => println!("提示: 代理 {} 没有 localPort，已跳过", proxy.name),
+            }
+        }
+        let fallback_proxies: Vec<String> = proxies
+            .iter()
+            .filter(|p| p.protocol == "xtcp")
+            .filter_map(|xtcp| {
+                let name = stcp_fallback_name(&xtcp.name);
+                proxies
+                    .iter()
+                    .any(|p| {
+                        p.name == name
+                            && p.protocol == "stcp"
+                            && p.local_port == xtcp.local_port
+                            && p.secret_key == xtcp.secret_key
+                    })
+                    .then_some(name)
+            })
+            .collect();
+        proxies.retain(|p| !fallback_proxies.contains(&p.name));
+        for proxy in &mut proxies {
+            proxy.stcp_fallback = fallback_proxies.contains(&stcp_fallback_name(&proxy.name));
+        }
+
+        // 只用作回退、不在本地监听的 stcp visitor
+        let fallback_visitors: Vec<&str> = file
+            .visitors
+            .iter()
+            .filter(|v| v.visitor_type == "stcp" && v.bind_port.is_some_and(|port| port <= 0))
+            .map(|v| v.name.as_str())
+            .collect();
+        let mut visitors = Vec::new();
+        for visitor in &file.visitors {
+            if fallback_visitors.contains(&visitor.name.as_str()) {
+                continue;
+            }
+            let bind_port = visitor.bind_port.and_then(|port| u16::try_from(port).ok());
+            let (Some(secret_key), Some(bind_port)) = (visitor.secret_key.clone(), bind_port) else {
+                println!("提示: visitor {} 没有 secretKey 或 bindPort，已跳过", visitor.name);
+                continue;
+            };
+            let stcp_fallback = match visitor.fallback_to {
+                Some(ref target)
+                    if *target == stcp_fallback_name(&visitor.name)
+                        && fallback_visitors.contains(&target.as_str()) =>
+                {
+                    true
+                }
+                Some(ref target) => {
+                    println!(
+                        "提示: visitor {} 的回退目标 {} 不是 {} 形式的 stcp visitor，已忽略回退",
+                        visitor.name,
+                        target,
+                        stcp_fallback_name(&visitor.name)
+                    );
+                    false
+                }
+                None => false,
+            };
+            visitors.push(VisitorConfig {
+                name: visitor.name.clone(),
+                protocol: visitor.visitor_type.clone(),
+                server_name: visitor.server_name.clone(),
+                server_user: visitor.server_user.clone(),
+                secret_key,
+                bind_addr: visitor.bind_addr.clone(),
+                bind_port,
+                stcp_fallback,
+                fallback_timeout_ms: visitor.fallback_timeout_ms.filter(|_| stcp_fallback),
+                keep_tunnel_open: visitor.keep_tunnel_open,
+            });
+        }
+
+        let (token, token_env) = match file.auth.token {
+            Some(token) => match env_template_name(&token) {
+                Some(var) => (None, Some(var.to_string())),
+                None => (Some(token), None),
+            },
+            None => (None, None),
+        };
+
+        Ok(FrpConfig {
+            server_addr: file.server_addr.unwrap_or(defaults.server_addr),
+            server_port: file.server_port.unwrap_or(defaults.server_port),
+            token,
+            token_env,
+            proxies,
+            visitors,
+            config_file: None,
+            version: None,
+            user: file.user,
+            login_fail_exit: file.login_fail_exit,
+            log_level: file.log.level,
+            transport: TransportConfig {
+                protocol: file.transport.protocol,
+                tls: file.transport.tls.enable,
+                pool_count: file.transport.pool_count,
+                heartbeat_interval: file.transport.heartbeat_interval,
+                heartbeat_timeout: file.transport.heartbeat_timeout,
+            },
+            admin_port: None,
+        })
+    }
+
+    /// 检查配置，生成 frpc.toml 和保存配置前调用
+    pub fn validate(&self) -> Result<()> {
+        if self.server_addr.trim().is_empty() {
+            return Err(anyhow::anyhow!("服务器地址不能为空"));
+        }
+        if self.server_port == 0 {
+            return Err(anyhow::anyhow!("服务器端口不能为 0"));
+        }
+        if let Some(ref user) = self.user {
+            if user.trim().is_empty() {
+                return Err(anyhow::anyhow!("用户名不能为空"));
+            }
+        }
+        if let Some(ref level) = self.log_level {
+            if !LOG_LEVELS.contains(&level.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "不支持的日志级别: {} (可用: {})",
+                    level,
+                    LOG_LEVELS.join(", ")
+                ));
+            }
+        }
+        if let Some(ref version) = self.version {
+            frp_download::parse_version(version)?;
+        }
+        self.transport.validate().context("传输设置无效")?;
+        if self.admin_port == Some(0) {
+            return Err(anyhow::anyhow!("管理接口端口不能为 0"));
+        }
+
+        // frpc 要求代理和 visitor 的名称互不相同
+        let mut names = HashSet::new();
+        for proxy in &self.proxies {
+            proxy
+                .validate()
+                .with_context(|| format!("代理 {} 配置无效", proxy.name))?;
+            if !names.insert(proxy.name.clone()) {
+                return Err(anyhow::anyhow!("代理名称重复或与 stcp 回退名称冲突: {}", proxy.name));
+            }
+            if let Some(fallback) = proxy.fallback_name() {
+                if !names.insert(fallback.clone()) {
+                    return Err(anyhow::anyhow!("代理 {} 的 stcp 回退名称 {} 已被使用", proxy.name, fallback));
+                }
+            }
+        }
+        for visitor in &self.visitors {
+            visitor
+                .validate()
+                .with_context(|| format!("visitor {} 配置无效", visitor.name))?;
+            if !names.insert(visitor.name.clone()) {
+                return Err(anyhow::anyhow!("visitor 名称与已有代理、visitor 或 stcp 回退名称重复: {}", visitor.name));
+            }
+            if let Some(fallback) = visitor.fallback_name() {
+                if !names.insert(fallback.clone()) {
+                    return Err(anyhow::anyhow!("visitor {} 的 stcp 回退名称 {} 已被使用", visitor.name, fallback));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 按名称设置客户端选项，值为 `default` 时恢复 frpc 默认值
+    ///
+    /// 可用的选项见 `FRP_OPTIONS`。
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<()> {
+        let value = Some(value).filter(|v| *v != "default");
+        match key {
+            "user" => self.user = value.map(str::to_string),
+            "login_fail_exit" => self.login_fail_exit = value.map(parse_bool).transpose()?,
+            "log_level" => self.log_level = value.map(str::to_string),
+            "transport.protocol" => self.transport.protocol = value.map(str::to_string),
+            "transport.tls" => self.transport.tls = value.map(parse_bool).transpose()?,
+            "transport.pool_count" => self.transport.pool_count = value.map(parse_number).transpose()?,
+            "transport.heartbeat_interval" => {
+                self.transport.heartbeat_interval = value.map(parse_number).transpose()?
+            }
+            "transport.heartbeat_timeout" => {
+                self.transport.heartbeat_timeout = value.map(parse_number).transpose()?
+            }
+            "admin_port" => self.admin_port = value.map(parse_number).transpose()?,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "未知的 frp 选项: {} (可用: {})",
+                    other,
+                    FRP_OPTIONS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否配置了 token（直接设置或来自环境变量）
+    pub fn has_token(&self) -> bool {
+        self.token.is_some() || self.token_env.is_some()
+    }
+
+    /// 实际使用的 token：设置了 `token_env` 时读取该环境变量
+    pub fn resolve_token(&self) -> Result<Option<String>> {
+        match self.token_env {
+            Some(ref var) => std::env::var(var)
+                .map(Some)
+                .with_context(|| format!("环境变量 {} 未设置，无法读取 frp token", var)),
+            None => Ok(self.token.clone()),
+        }
+    }
+
+    /// 按名称查找代理
+    pub fn proxy(&self, name: &str) -> Option<&ProxyConfig> {
+        self.proxies.iter().find(|p| p.name == name)
+    }
+
+    /// 添加代理，名称不能与已有代理重复
+    pub fn add_proxy(&mut self, proxy: ProxyConfig) -> Result<()> {
+        proxy.validate()?;
+        if self.proxy(&proxy.name).is_some() {
+            return Err(anyhow::anyhow!("代理 {} 已存在", proxy.name));
+        }
+        self.proxies.push(proxy);
+        Ok(())
+    }
+
+    /// 按名称删除代理
+    pub fn remove_proxy(&mut self, name: &str) -> Result<ProxyConfig> {
+        let index = self
+            .proxies
+            .iter()
+            .position(|p| p.name == name)
+            .with_context(|| format!("没有名为 {} 的代理", name))?;
+        Ok(self.proxies.remove(index))
+    }
+
+    /// 设置 NChat 自身代理的类型，没有该代理时按 `local_port` 新建
+    ///
+    /// 只能使用 `NCHAT_PROXY_TYPES` 中的类型。改为 sudp 时没有 secret key 则自动生成，
+    /// 并去掉不再使用的远程端口。
+    pub fn set_nchat_protocol(&mut self, protocol: &str, local_port: u16) -> Result<()> {
+        validate_nchat_proxy_type(protocol)?;
+        let index = match self.proxies.iter().position(|p| p.name == NCHAT_PROXY_NAME) {
+            Some(index) => index,
+            None => {
+                self.proxies.push(ProxyConfig {
+                    name: NCHAT_PROXY_NAME.to_string(),
+                    local_port,
+                    ..ProxyConfig::default()
+                });
+                self.proxies.len() - 1
+            }
+        };
+        let proxy = &mut self.proxies[index];
+        proxy.protocol = protocol.to_string();
+        proxy.stcp_fallback = false;
+        if proxy.is_secret() {
+            proxy.remote_port = None;
+            if proxy.secret_key.is_none() {
+                proxy.secret_key = Some(generate_secret_key());
+            }
+        }
+        Ok(())
+    }
+
+    /// 按名称查找 visitor
+    pub fn visitor(&self, name: &str) -> Option<&VisitorConfig> {
+        self.visitors.iter().find(|v| v.name == name)
+    }
+
+    /// 添加 visitor，名称不能与已有代理或 visitor 重复
+    pub fn add_visitor(&mut self, visitor: VisitorConfig) -> Result<()> {
+        visitor.validate()?;
+        if self.visitor(&visitor.name).is_some() || self.proxy(&visitor.name).is_some() {
+            return Err(anyhow::anyhow!("名称 {} 已被代理或 visitor 使用", visitor.name));
+        }
+        self.visitors.push(visitor);
+        Ok(())
+    }
+
+    /// 按名称删除 visitor
+    pub fn remove_visitor(&mut self, name: &str) -> Result<VisitorConfig> {
+        let index = self
+            .visitors
+            .iter()
+            .position(|v| v.name == name)
+            .with_context(|| format!("没有名为 {} 的 visitor", name))?;
+        Ok(self.visitors.remove(index))
+    }
+
+    /// 生成供对端 NChat 执行的 visitor 命令，用于访问本地的 stcp/sudp/xtcp 代理
+    ///
+    /// xtcp 代理未开启 stcp 回退时，对端 visitor 同样不回退。
+    /// `bind_port` 为对端本地监听端口，未指定时为代理本地端口加 1，避免与对端自己的接收端口冲突。
+    /// NChat 自身代理（例如从旧配置加载的 stcp 代理）不是 sudp 时拒绝生成，对端无法通过它发送 UDP 消息。
+    pub fn visitor_command(&self, proxy_name: &str, bind_port: Option<u16>) -> Result<String> {
+        let proxy = self
+            .proxy(proxy_name)
+            .with_context(|| format!("没有名为 {} 的代理", proxy_name))?;
+        if proxy.name == NCHAT_PROXY_NAME && proxy.protocol != "sudp" {
+            return Err(anyhow::anyhow!(
+                "代理 {} 的类型为 {}，对端无法通过它发送 UDP 消息，请先用 frp config <服务器地址> <端口> --type sudp 改为 sudp",
+                proxy.name,
+                proxy.protocol
+            ));
+        }
+        if !VISITOR_TYPES.contains(&proxy.protocol.as_str()) {
+            return Err(anyhow::anyhow!(
+                "代理 {} 的类型为 {}，只有 {} 代理可以通过 visitor 访问",
+                proxy.name,
+                proxy.protocol,
+                VISITOR_TYPES.join("/")
+            ));
+        }
+        let secret_key = proxy
+            .secret_key
+            .as_deref()
+            .with_context(|| format!("代理 {} 没有 secret key", proxy.name))?;
+        let mut command = format!(
+            "frp visitor add {}-visitor {} {} {} {}",
+            proxy.name,
+            proxy.protocol,
+            proxy.name,
+            secret_key,
+            bind_port.unwrap_or_else(|| default_visitor_port(proxy.local_port))
+        );
+        if let Some(ref user) = self.user {
+            command.push_str(&format!(" --server-user {}", user));
+        }
+        if proxy.protocol == "xtcp" && !proxy.stcp_fallback {
+            command.push_str(" --no-fallback");
+        }
+        Ok(command)
+    }
+
+    /// 除代理和 visitor 外的设置是否相同，相同时可以通过 frpc 管理接口热重载
+    pub fn same_client_settings(&self, other: &FrpConfig) -> bool {
+        let strip = |config: &FrpConfig| FrpConfig {
+            proxies: Vec::new(),
+            visitors: Vec::new(),
+            ..config.clone()
+        };
+        strip(self) == strip(other)
+    }
+
+    /// 生成的 frpc.toml 中所有代理和 visitor 的名称（包括 stcp 回退项）
+    pub fn frpc_names(&self) -> Vec<String> {
+        let proxies = self.proxies.iter().flat_map(|p| std::iter::once(p.name.clone()).chain(p.fallback_name()));
+        let visitors = self.visitors.iter().flat_map(|v| std::iter::once(v.name.clone()).chain(v.fallback_name()));
+        proxies.chain(visitors).collect()
+    }
+
+    /// 用于状态输出的副本：token 和 secret key 替换为掩码
+    pub fn redacted(&self) -> FrpConfig {
+        let mut config = self.clone();
+        config.token = config.token.map(|_| TOKEN_MASK.to_string());
+        for proxy in &mut config.proxies {
+            proxy.secret_key = proxy.secret_key.take().map(|_| TOKEN_MASK.to_string());
+        }
+        for visitor in &mut config.visitors {
+            visitor.secret_key = TOKEN_MASK.to_string();
+        }
+        config
+    }
+
+    /// 把 NChat 自身代理的本地端口设为接收端口（没有该代理时不做修改）
+    pub fn set_nchat_port(&mut self, port: u16) {
+        if let Some(proxy) = self.proxies.iter_mut().find(|p| p.name == NCHAT_PROXY_NAME) {
+            proxy.local_port = port;
+        }
+    }
+
+    /// 把配置保存到 `dir`，下次运行时可通过 `load_saved` 读取
+    ///
+    /// token 不写入设置文件，而是单独写入 `token` 文件（Unix 上权限为 0600）。
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).context("创建 frp 配置目录失败")?;
+
+        let settings = FrpConfig { token: None, ..self.clone() };
+        let content = toml::to_string(&settings).context("序列化 frp 配置失败")?;
+        // 代理和 visitor 的 secret key 保存在设置文件中，同样只允许当前用户读写
+        write_private_file(&dir.join(SAVED_CONFIG_FILE), content.as_bytes()).context("保存 frp 配置失败")?;
+
+        let token_path = dir.join(TOKEN_FILE);
+        match self.token {
+            Some(ref token) => write_private_file(&token_path, token.as_bytes())
+                .context("保存 frp token 失败")?,
+            None => {
+                if token_path.exists() {
+                    fs::remove_file(&token_path).context("删除旧的 frp token 失败")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 读取 `save` 保存的配置，从未保存过时返回 `None`
+    pub fn load_saved(dir: &Path) -> Result<Option<FrpConfig>> {
+        let path = dir.join(SAVED_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("读取 {} 失败", path.display()))?;
+        let mut config: FrpConfig = toml::from_str(&content)
+            .with_context(|| format!("解析 {} 失败", path.display()))?;
+
+        let token_path = dir.join(TOKEN_FILE);
+        if token_path.exists() {
+            let token = fs::read_to_string(&token_path)
+                .with_context(|| format!("读取 {} 失败", token_path.display()))?;
+            config.token = Some(token.trim().to_string()).filter(|t| !t.is_empty());
+        }
+        Ok(Some(config))
+    }
+}
+
+impl ProxyConfig {
+    /// 从命令参数 `<名称> <类型> <本地端口> [远程端口] [选项]` 解析代理配置
+    ///
+    /// stcp/sudp/xtcp 代理未用 `--sk` 指定 secret key 时自动生成；
+    /// xtcp 代理默认开启 stcp 回退，`--no-fallback` 关闭。
+    pub fn from_args(args: &[&str]) -> Result<Self> {
+        let mut positional = Vec::new();
+        let mut local_ip = None;
+        let mut use_encryption = false;
+        let mut use_compression = false;
+        let mut secret_key = None;
+        let mut allow_users = Vec::new();
+        let mut no_fallback = false;
+        let mut iter = args.iter();
+        while let Some(&arg) = iter.next() {
+            match arg {
+                "--local-ip" => {
+                    let value = iter.next().context("--local-ip 需要指定地址")?;
+                    local_ip = Some(value.to_string());
+                }
+                "--encrypt" => use_encryption = true,
+                "--compress" => use_compression = true,
+                "--sk" => {
+                    let value = iter.next().context("--sk 需要指定 secret key")?;
+                    secret_key = Some(value.to_string());
+                }
+                "--allow-user" => {
+                    let value = iter.next().context("--allow-user 需要指定用户名")?;
+                    allow_users.push(value.to_string());
+                }
+                "--no-fallback" => no_fallback = true,
+                other if other.starts_with("--") => {
+                    return Err(anyhow::anyhow!("未知的选项: {}", other));
+                }
+                other => positional.push(other),
+            }
+        }
+
+        let (name, protocol, local_port) = match positional[..] {
+            [name, protocol, local_port, ..] => (name, protocol, local_port),
+            _ => return Err(anyhow::anyhow!("用法: {}", PROXY_ADD_USAGE)),
+        };
+        validate_proxy_type(protocol)?;
+        let local_port = local_port
+            .parse::<u16>()
+            .with_context(|| format!("无效的本地端口: {}", local_port))?;
+        let remote_port = match positional.get(3) {
+            Some(port) => Some(
+                port.parse::<u16>()
+                    .with_context(|| format!("无效的远程端口: {}", port))?,
+            ),
+            None => None,
+        };
+        let mut proxy = ProxyConfig {
+            name: name.to_string(),
+            protocol: protocol.to_string(),
+            local_port,
+            remote_port,
+            local_ip,
+            use_encryption,
+            use_compression,
+            secret_key,
+            allow_users,
+            stcp_fallback: protocol == "xtcp" && !no_fallback,
+        };
+        if proxy.is_secret() && proxy.secret_key.is_none() {
+            proxy.secret_key = Some(generate_secret_key());
+        }
+        proxy.validate()?;
+        Ok(proxy)
+    }
+
+    /// 是否为需要 secret key 的代理类型（stcp/sudp/xtcp）
+    pub fn is_secret(&self) -> bool {
+        SECRET_PROXY_TYPES.contains(&self.protocol.as_str())
+    }
+
+    /// 开启 stcp 回退时，生成的 stcp 代理名称
+    pub fn fallback_name(&self) -> Option<String> {
+        self.stcp_fallback.then(|| stcp_fallback_name(&self.name))
+    }
+
+    /// 检查代理配置
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("代理名称不能为空"));
+        }
+        if self.name == NCHAT_PROXY_NAME {
+            validate_nchat_proxy_type(&self.protocol)?;
+        } else {
+            validate_proxy_type(&self.protocol)?;
+        }
+        if self.local_port == 0 {
+            return Err(anyhow::anyhow!("本地端口不能为 0"));
+        }
+        if self.is_secret() {
+            if self.secret_key.as_deref().is_none_or(str::is_empty) {
+                return Err(anyhow::anyhow!("{} 代理需要 secret key", self.protocol));
+            }
+            if self.remote_port.is_some() {
+                return Err(anyhow::anyhow!("{} 代理不在服务器上开放远程端口，不能设置远程端口", self.protocol));
+            }
+        }
+        if self.stcp_fallback && self.protocol != "xtcp" {
+            return Err(anyhow::anyhow!("stcp 回退只适用于 xtcp 代理"));
+        }
+        if let Some(ref ip) = self.local_ip {
+            let valid_host = !ip.is_empty()
+                && ip.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+            if ip.parse::<IpAddr>().is_err() && !valid_host {
+                return Err(anyhow::anyhow!("无效的本地地址: {}", ip));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `frp proxy add` 命令用法
+pub const PROXY_ADD_USAGE: &str =
+    "frp proxy add <名称> <类型> <本地端口> [远程端口] [--local-ip 地址] [--encrypt] [--compress] [--sk 密钥] [--allow-user 用户] [--no-fallback]";
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        other => Err(anyhow::anyhow!("无效的开关值: {} (可用: true、false)", other)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T> {
+    value.parse().map_err(|_| anyhow::anyhow!("无效的数值: {}", value))
+}
+
+/// 把字符串写成 TOML 字符串字面量（带引号并转义）
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+/// 若 `value` 是 `{{ .Envs.变量名 }}` 形式的 frp 模板，返回变量名
+fn env_template_name(value: &str) -> Option<&str> {
+    value
+        .trim()
+        .strip_prefix("{{")?
+        .strip_suffix("}}")?
+        .trim()
+        .strip_prefix(".Envs.")
+        .map(str::trim)
+}
+
+/// 写入只有当前用户可读写的文件（非 Unix 系统上按普通文件写入）
+fn write_private_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let mut file = options.open(path)?;
+        // 文件已存在时 mode 不生效，需要单独收紧权限
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(content)
+    }
+    #[cfg(not(unix))]
+    {
+        options.open(path)?.write_all(content)
+    }
+}
+
+/// Frp 进程指标（可在多个管理器之间共享，供指标导出使用）
+#[derive(Debug, Default)]
+pub struct FrpMetrics {
+    running: AtomicBool,
+    starts: AtomicU64,
+    restarts: AtomicU64,
+}
+
+impl FrpMetrics {
+    /// frpc 进程是否在运行
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// frpc 进程启动次数
+    pub fn starts(&self) -> u64 {
+        self.starts.load(Ordering::Relaxed)
+    }
+
+    /// frpc 进程重启次数（首次启动之后的每次启动）
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    fn record_start(&self) {
+        if self.starts.fetch_add(1, Ordering::Relaxed) > 0 {
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+        self.running.store(true, Ordering::SeqCst);
+    }
+
+    fn record_stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+/// frp 使用的目录和文件位置
+///
+/// 相对路径相对于当前工作目录。同时运行多个 NChat 实例时，
+/// 为每个实例设置不同的 `config_dir` 和 `log_file`。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrpPaths {
+    /// 保存 NChat frp 设置、token 和生成的 frpc.toml 的目录
+    pub config_dir: PathBuf,
+    /// frpc 输出的日志文件
+    pub log_file: PathBuf,
+    /// `frp download` 安装 frpc 的托管目录
+    pub tools_dir: PathBuf,
+    /// 查找 frpc 的目录，先于托管目录查找
+    pub search_path: Vec<PathBuf>,
+    /// 以上位置都找不到时是否在 PATH 中查找 frpc
+    pub system_path: bool,
+}
+
+impl Default for FrpPaths {
+    fn default() -> Self {
+        Self {
+            config_dir: PathBuf::from(FRP_CONFIG_DIR),
+            log_file: PathBuf::from(FRP_LOG_FILE),
+            tools_dir: PathBuf::from(frp_download::DEFAULT_TOOLS_DIR),
+            search_path: vec![PathBuf::from(".")],
+            system_path: true,
+        }
+    }
+}
+
+impl FrpPaths {
+    /// 生成的 frpc 配置文件路径
+    pub fn frpc_config(&self) -> PathBuf {
+        self.config_dir.join("frpc.toml")
+    }
+
+    /// 查找 frpc，`version` 指定托管目录中的版本
+    ///
+    /// 依次查找 `search_path` 中的目录、托管目录中的 `version` 版本
+    /// （未指定版本时为已安装的最新版本），最后是 PATH。
+    pub fn locate_frpc(&self, version: Option<&str>) -> Result<PathBuf> {
+        let file_name = frp_download::frpc_file_name();
+        let mut searched = Vec::new();
+
+        for dir in &self.search_path {
+            let path = dir.join(file_name);
+            if path.is_file() {
+                return Ok(path);
+            }
+            searched.push(path);
+        }
+
+        let version = match version {
+            Some(version) => Some(frp_download::parse_version(version)?),
+            None => frp_download::installed_versions(&self.tools_dir).into_iter().next(),
+        };
+        if let Some(version) = version {
+            let path = frp_download::installed_frpc_path(&self.tools_dir, &version);
+            if path.is_file() {
+                return Ok(path);
+            }
+            searched.push(path);
+        }
+
+        if self.system_path {
+            if let Some(paths) = std::env::var_os("PATH") {
+                if let Some(path) = std::env::split_paths(&paths)
+                    .map(|dir| dir.join(file_name))
+                    .find(|path| path.is_file())
+                {
+                    return Ok(path);
+                }
+            }
+            searched.push(PathBuf::from("PATH"));
+        }
+
+        let searched: Vec<String> = searched.iter().map(|p| p.display().to_string()).collect();
+        Err(anyhow::anyhow!(
+            "未找到 frp 客户端 (已查找: {})\n请使用 frp download 下载，或将 frpc 放在查找目录中",
+            searched.join(", ")
+        ))
+    }
 }
 
 /// Frp 客户端管理器
 pub struct FrpManager {
     config: FrpConfig,
+    paths: FrpPaths,
     process: Arc<Mutex<Option<Child>>>,
     config_path: PathBuf,
     frp_path: Option<PathBuf>,
+    metrics: Arc<FrpMetrics>,
+    health: Arc<Mutex<FrpHealth>>,
+    /// 从 frpc 日志解析出的连接状态
+    connection: Arc<Mutex<FrpConnection>>,
+    status_sender: Option<Sender<String>>,
+    /// 当前监控线程和日志跟踪线程的停止标志
+    supervisor_stop: Option<Arc<AtomicBool>>,
+    /// 本次启动的 frpc 管理接口，原样使用用户配置文件时为空
+    admin: Option<AdminApi>,
+    /// 运行中修改了无法热重载的设置，重启后才会生效
+    restart_pending: bool,
+}
+
+/// `FrpManager::apply_config` 的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigApplied {
+    /// frpc 未运行，下次启动时使用新配置
+    Saved,
+    /// 已通过管理接口热重载代理和 visitor
+    Reloaded,
+    /// 服务器或客户端设置有变化，需要重启 frpc 才会生效
+    RestartRequired,
+}
+
+/// frpc 进程状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrpState {
+    /// 未启动或已被主动停止
+    Stopped,
+    Running,
+    /// 进程意外退出，等待自动重启
+    Restarting,
+    /// 连续重启失败次数过多，已放弃重启
+    Failed,
+}
+
+impl FrpState {
+    /// 状态的中文描述
+    pub fn label(&self) -> &'static str {
+        match self {
+            FrpState::Stopped => "已停止",
+            FrpState::Running => "运行中",
+            FrpState::Restarting => "等待重启",
+            FrpState::Failed => "已失败",
+        }
+    }
+}
+
+/// 由监控线程维护的进程健康信息
+#[derive(Debug)]
+struct FrpHealth {
+    state: FrpState,
+    /// 最近一次意外退出的退出状态
+    last_exit: Option<String>,
+}
+
+/// 启动 frpc 所需的参数
+#[derive(Clone)]
+struct FrpLaunch {
+    frp_path: PathBuf,
+    config_path: PathBuf,
+    token: Option<String>,
+    log_file: PathBuf,
+}
+
+impl FrpLaunch {
+    fn spawn(&self) -> std::io::Result<Child> {
+        let mut command = Command::new(&self.frp_path);
+        command.arg("-c");
+        command.arg(&self.config_path);
+        if let Some(ref token) = self.token {
+            command.env(TOKEN_ENV, token);
+        }
+
+        // 标准输出和错误输出追加到同一个日志文件，避免干扰主程序交互
+        let log_file = OpenOptions::new().create(true).append(true).open(&self.log_file)?;
+        command.stderr(Stdio::from(log_file.try_clone()?));
+        command.stdout(Stdio::from(log_file));
+
+        command.spawn()
+    }
+}
+
+/// frpc 进程监控：检测意外退出并按退避时间自动重启
+struct Supervisor {
+    launch: FrpLaunch,
+    process: Arc<Mutex<Option<Child>>>,
+    health: Arc<Mutex<FrpHealth>>,
+    connection: Arc<Mutex<FrpConnection>>,
+    metrics: Arc<FrpMetrics>,
+    status_sender: Option<Sender<String>>,
+    stop: Arc<AtomicBool>,
+    /// 生成的配置中启用的管理接口，端口被占用时换用新端口
+    admin: Option<AdminApi>,
+    /// 最近一次启动 frpc 时日志文件的长度，之后的内容为该进程的输出
+    log_offset: u64,
+}
+
+impl Supervisor {
+    fn report(&self, msg: String) {
+        match self.status_sender {
+            Some(ref sender) => {
+                let _ = sender.send(msg);
+            }
+            None => println!("{}", msg),
+        }
+    }
+
+    fn set_state(&self, state: FrpState) {
+        self.health.lock().unwrap().state = state;
+    }
+
+    /// frpc 退出前的输出中是否有管理接口端口被占用的错误
+    fn admin_bind_failed(&self, admin: &AdminApi, offset: u64) -> bool {
+        let Ok(mut file) = fs::File::open(&self.launch.log_file) else {
+            return false;
+        };
+        if file.seek(SeekFrom::Start(offset)).is_err() {
+            return false;
+        }
+        BufReader::new(file.take(MAX_EXIT_OUTPUT))
+            .lines()
+            .map_while(|line| line.ok())
+            .any(|line| admin.is_bind_failure(&line))
+    }
+
+    /// 管理接口端口被占用时换用新端口，避免每次重启都因同一端口失败
+    fn recover_admin_port(&self, offset: u64) {
+        let Some(ref admin) = self.admin else {
+            return;
+        };
+        if !self.admin_bind_failed(admin, offset) {
+            return;
+        }
+        let old_port = admin.port();
+        match admin.switch_port(&self.launch.config_path) {
+            Ok(port) => self.report(format!(
+                "frpc 管理接口端口 {} 已被占用，改用端口 {}",
+                old_port, port
+            )),
+            Err(e) => self.report(format!("{:#}", e)),
+        }
+    }
+
+    fn run(self) {
+        let mut log_offset = self.log_offset;
+        let mut backoff = INITIAL_RESTART_BACKOFF;
+        let mut failures = 0u32;
+        let mut started_at = Instant::now();
+
+        loop {
+            thread::sleep(SUPERVISE_INTERVAL);
+            if self.stop.load(Ordering::SeqCst) {
+                return;
+            }
+
+            // 检查进程是否退出
+            let exit_status = {
+                let mut guard = self.process.lock().unwrap();
+                let child = match guard.as_mut() {
+                    Some(child) => child,
+                    None => return,
+                };
+                match child.try_wait() {
+                    Ok(None) => None,
+                    Ok(Some(status)) => {
+                        *guard = None;
+                        Some(status.to_string())
+                    }
+                    Err(e) => Some(format!("无法获取进程状态: {}", e)),
+                }
+            };
+            let exit_status = match exit_status {
+                Some(status) => status,
+                None => {
+                    // 稳定运行一段时间后重置退避
+                    if started_at.elapsed() >= STABLE_RUN_TIME {
+                        backoff = INITIAL_RESTART_BACKOFF;
+                        failures = 0;
+                    }
+                    continue;
+                }
+            };
+            if self.stop.load(Ordering::SeqCst) {
+                return;
+            }
+
+            self.metrics.record_stop();
+            self.health.lock().unwrap().last_exit = Some(exit_status.clone());
+            *self.connection.lock().unwrap() = FrpConnection::default();
+            self.report(format!("Frp 客户端意外退出 ({})", exit_status));
+            self.recover_admin_port(log_offset);
+
+            // 重启，启动失败时继续退避重试
+            loop {
+                if failures >= MAX_RESTART_ATTEMPTS {
+                    self.set_state(FrpState::Failed);
+                    self.report(format!(
+                        "Frp 客户端连续 {} 次重启后仍然退出，已停止自动重启，请检查 {}",
+                        failures,
+                        self.launch.log_file.display()
+                    ));
+                    return;
+                }
+                failures += 1;
+                self.set_state(FrpState::Restarting);
+                self.report(format!(
+                    "将在 {} 秒后重启 frp 客户端 (第 {} 次)",
+                    backoff.as_secs(),
+                    failures
+                ));
+                if !self.sleep_unless_stopped(backoff) {
+                    return;
+                }
+                backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+
+                log_offset = fs::metadata(&self.launch.log_file).map(|m| m.len()).unwrap_or(0);
+                match self.launch.spawn() {
+                    Ok(mut child) => {
+                        let mut guard = self.process.lock().unwrap();
+                        if self.stop.load(Ordering::SeqCst) {
+                            // 等待期间被主动停止
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            return;
+                        }
+                        let pid = child.id();
+                        *guard = Some(child);
+                        drop(guard);
+                        self.metrics.record_start();
+                        self.set_state(FrpState::Running);
+                        started_at = Instant::now();
+                        self.report(format!("Frp 客户端已重启 (PID: {})", pid));
+                        break;
+                    }
+                    Err(e) => self.report(format!("重启 frp 客户端失败: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// 等待指定时间，期间被主动停止时返回 false
+    fn sleep_unless_stopped(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            if self.stop.load(Ordering::SeqCst) {
+                return false;
+            }
+            thread::sleep(SUPERVISE_INTERVAL.min(deadline - Instant::now()));
+        }
+        !self.stop.load(Ordering::SeqCst)
+    }
 }
 
 impl FrpManager {
-    /// 创建新的 Frp 管理器
-    pub fn new(config: FrpConfig) -> Result<Self> {
-        let config_dir = PathBuf::from("frp_config");
-        fs::create_dir_all(&config_dir)?;
+    /// 创建新的 Frp 管理器，配置文件、日志和 frpc 的位置由 `paths` 决定
+    pub fn new(config: FrpConfig, paths: FrpPaths) -> Result<Self> {
+        fs::create_dir_all(&paths.config_dir)
+            .with_context(|| format!("创建 frp 配置目录 {} 失败", paths.config_dir.display()))?;
         
-        let config_path = config_dir.join("frpc.toml");
+        let config_path = match config.config_file {
+            Some(ref path) => path.clone(),
+            None => paths.frpc_config(),
+        };
         
         Ok(Self {
             config,
+            paths,
             process: Arc::new(Mutex::new(None)),
             config_path,
             frp_path: None,
+            metrics: Arc::new(FrpMetrics::default()),
+            health: Arc::new(Mutex::new(FrpHealth {
+                state: FrpState::Stopped,
+                last_exit: None,
+            })),
+            connection: Arc::new(Mutex::new(FrpConnection::default())),
+            status_sender: None,
+            supervisor_stop: None,
+            admin: None,
+            restart_pending: false,
         })
     }
     
+    /// 使用共享的进程指标（替换管理器时保留累计计数）
+    pub fn set_metrics(&mut self, metrics: Arc<FrpMetrics>) {
+        self.metrics = metrics;
+    }
+    
+    /// 设置状态通道，进程意外退出、重启等状态变化会发送到该通道（未设置时直接输出）
+    pub fn set_status_sender(&mut self, sender: Sender<String>) {
+        self.status_sender = Some(sender);
+    }
+    
+    /// 替换配置，已运行的 frpc 进程不受影响，重启后生效
+    pub fn update_config(&mut self, config: FrpConfig) {
+        self.config_path = match config.config_file {
+            Some(ref path) => path.clone(),
+            None => self.paths.frpc_config(),
+        };
+        self.config = config;
+    }
+
+    /// 替换配置并尽量让正在运行的 frpc 使用新配置
+    ///
+    /// 只有代理和 visitor 变化时重新生成 frpc.toml 并通过管理接口热重载，不中断已有连接；
+    /// 服务器、token 等设置变化时 frpc 不支持热重载，返回 `RestartRequired`。
+    /// 热重载失败时新配置仍会保留，并标记为需要重启。
+    pub fn apply_config(&mut self, config: FrpConfig) -> Result<ConfigApplied> {
+        if !self.is_running() {
+            self.update_config(config);
+            return Ok(ConfigApplied::Saved);
+        }
+        let reloadable = self.admin.is_some()
+            && self.config.config_file.is_none()
+            && self.config.same_client_settings(&config);
+        self.update_config(config);
+        if !reloadable {
+            self.restart_pending = true;
+            return Ok(ConfigApplied::RestartRequired);
+        }
+        if let Err(e) = self.reload() {
+            self.restart_pending = true;
+            return Err(e);
+        }
+        Ok(ConfigApplied::Reloaded)
+    }
+
+    /// 重新生成 frpc.toml 并通知 frpc 重新加载
+    pub fn reload(&self) -> Result<()> {
+        let admin = self
+            .admin
+            .as_ref()
+            .context("frpc 管理接口不可用，请重启 frp")?;
+        self.generate_config()?;
+        admin.reload().context("frpc 重新加载配置失败")?;
+
+        // 去掉已删除的代理和 visitor 的状态
+        let names = self.config.frpc_names();
+        let mut connection = self.connection.lock().unwrap();
+        connection.started_proxies.retain(|name| names.contains(name));
+        connection.proxy_errors.retain(|e| names.contains(&e.name));
+        connection.p2p.retain(|p| names.contains(&p.name));
+        Ok(())
+    }
+
+    /// 通过管理接口查询 frpc 中代理的实时状态
+    ///
+    /// 会向 frpc 发送 HTTP 请求（最长等待数秒），frpc 未运行或未启用管理接口时返回 `None`。
+    /// `get_status` 不会调用此方法，需要实时状态时单独调用。
+    pub fn live_proxy_states(&self) -> Option<Result<Vec<ProxyState>>> {
+        self.live_admin().map(|admin| admin.proxy_status())
+    }
+
+    /// frpc 正在运行且启用了管理接口时，返回管理接口句柄
+    ///
+    /// 句柄可在释放 `FrpManager` 所在的锁之后使用。
+    pub fn live_admin(&self) -> Option<AdminApi> {
+        let admin = self.admin.as_ref()?;
+        if !self.is_running() {
+            return None;
+        }
+        Some(admin.clone())
+    }
+
     /// 设置 frp 客户端路径
     pub fn set_frp_path(&mut self, path: PathBuf) {
         self.frp_path = Some(path);
     }
     
     /// 生成 frp 配置文件
+    ///
+    /// 使用用户提供的配置文件时不会生成，避免覆盖该文件。
     pub fn generate_config(&self) -> Result<()> {
+        if let Some(ref path) = self.config.config_file {
+            return Err(anyhow::anyhow!("正在原样使用配置文件 {}，不会重新生成", path.display()));
+        }
+        self.config.validate()?;
+        if self.config.proxies.is_empty() && self.config.visitors.is_empty() {
+            return Err(anyhow::anyhow!("没有配置任何代理或 visitor，请先使用 frp proxy add 或 frp visitor add 添加"));
+        }
         let mut config_content = String::new();
         
         // 服务器配置
-        config_content.push_str(&format!("serverAddr = \"{}\"\n", self.config.server_addr));
+        config_content.push_str(&format!("serverAddr = {}\n", toml_string(&self.config.server_addr)));
         config_content.push_str(&format!("serverPort = {}\n", self.config.server_port));
+        if let Some(ref user) = self.config.user {
+            config_content.push_str(&format!("user = {}\n", toml_string(user)));
+        }
+        if let Some(login_fail_exit) = self.config.login_fail_exit {
+            config_content.push_str(&format!("loginFailExit = {}\n", login_fail_exit));
+        }
         
-        if let Some(ref token) = self.config.token {
-            config_content.push_str(&format!("auth.token = \"{}\"\n", token));
+        // token 不直接写入配置文件，启动时由环境变量提供
+        if self.config.has_token() {
+            config_content.push_str(&format!("auth.token = \"{{{{ .Envs.{} }}}}\"\n", TOKEN_ENV));
+        }
+        if let Some(ref level) = self.config.log_level {
+            config_content.push_str(&format!("log.level = {}\n", toml_string(level)));
         }
         
-        // 代理配置
-        config_content.push_str("\n[[proxies]]\n");
-        config_content.push_str(&format!("name = \"{}\"\n", self.config.name));
-        config_content.push_str(&format!("type = \"{}\"\n", self.config.protocol));
-        config_content.push_str(&format!("localPort = {}\n", self.config.local_port));
+        // 传输设置
+        let transport = &self.config.transport;
+        if let Some(ref protocol) = transport.protocol {
+            config_content.push_str(&format!("transport.protocol = {}\n", toml_string(protocol)));
+        }
+        if let Some(tls) = transport.tls {
+            config_content.push_str(&format!("transport.tls.enable = {}\n", tls));
+        }
+        if let Some(pool_count) = transport.pool_count {
+            config_content.push_str(&format!("transport.poolCount = {}\n", pool_count));
+        }
+        if let Some(interval) = transport.heartbeat_interval {
+            config_content.push_str(&format!("transport.heartbeatInterval = {}\n", interval));
+        }
+        if let Some(timeout) = transport.heartbeat_timeout {
+            config_content.push_str(&format!("transport.heartbeatTimeout = {}\n", timeout));
+        }
+
+        // 管理接口，用于查询代理状态和热重载
+        if let Some(ref admin) = self.admin {
+            config_content.push_str(&admin.frpc_config());
+        }
         
-        if let Some(remote_port) = self.config.remote_port {
-            config_content.push_str(&format!("remotePort = {}\n", remote_port));
+        // 代理配置，开启 stcp 回退的 xtcp 代理额外生成一个相同设置的 stcp 代理
+        for proxy in &self.config.proxies {
+            let mut entries = vec![(proxy.name.clone(), proxy.protocol.as_str())];
+            if let Some(fallback) = proxy.fallback_name() {
+                entries.push((fallback, "stcp"));
+            }
+            for (name, protocol) in entries {
+                config_content.push_str("\n[[proxies]]\n");
+                config_content.push_str(&format!("name = {}\n", toml_string(&name)));
+                config_content.push_str(&format!("type = {}\n", toml_string(protocol)));
+                if let Some(ref local_ip) = proxy.local_ip {
+                    config_content.push_str(&format!("localIP = {}\n", toml_string(local_ip)));
+                }
+                config_content.push_str(&format!("localPort = {}\n", proxy.local_port));
+
+                if let Some(remote_port) = proxy.remote_port {
+                    config_content.push_str(&format!("remotePort = {}\n", remote_port));
+                }
+                if proxy.use_encryption {
+                    config_content.push_str("transport.useEncryption = true\n");
+                }
+                if proxy.use_compression {
+                    config_content.push_str("transport.useCompression = true\n");
+                }
+                if let Some(ref secret_key) = proxy.secret_key {
+                    config_content.push_str(&format!("secretKey = {}\n", toml_string(secret_key)));
+                }
+                if !proxy.allow_users.is_empty() {
+                    let users: Vec<String> = proxy.allow_users.iter().map(|u| toml_string(u)).collect();
+                    config_content.push_str(&format!("allowUsers = [{}]\n", users.join(", ")));
+                }
+            }
+        }
+
+        // visitor 配置
+        for visitor in &self.config.visitors {
+            config_content.push_str("\n[[visitors]]\n");
+            config_content.push_str(&format!("name = {}\n", toml_string(&visitor.name)));
+            config_content.push_str(&format!("type = {}\n", toml_string(&visitor.protocol)));
+            config_content.push_str(&format!("serverName = {}\n", toml_string(&visitor.server_name)));
+            if let Some(ref server_user) = visitor.server_user {
+                config_content.push_str(&format!("serverUser = {}\n", toml_string(server_user)));
+            }
+            config_content.push_str(&format!("secretKey = {}\n", toml_string(&visitor.secret_key)));
+            config_content.push_str(&format!("bindAddr = {}\n", toml_string(visitor.bind_addr())));
+            config_content.push_str(&format!("bindPort = {}\n", visitor.bind_port));
+            if visitor.keep_tunnel_open {
+                config_content.push_str("keepTunnelOpen = true\n");
+            }
+            if let Some(fallback) = visitor.fallback_name() {
+                config_content.push_str(&format!("fallbackTo = {}\n", toml_string(&fallback)));
+                if let Some(timeout) = visitor.fallback_timeout_ms {
+                    config_content.push_str(&format!("fallbackTimeoutMs = {}\n", timeout));
+                }
+
+                // 回退用的 stcp visitor 不在本地监听，只接收 xtcp visitor 转交的连接
+                config_content.push_str("\n[[visitors]]\n");
+                config_content.push_str(&format!("name = {}\n", toml_string(&fallback)));
+                config_content.push_str("type = \"stcp\"\n");
+                config_content.push_str(&format!(
+                    "serverName = {}\n",
+                    toml_string(&stcp_fallback_name(&visitor.server_name))
+                ));
+                if let Some(ref server_user) = visitor.server_user {
+                    config_content.push_str(&format!("serverUser = {}\n", toml_string(server_user)));
+                }
+                config_content.push_str(&format!("secretKey = {}\n", toml_string(&visitor.secret_key)));
+                config_content.push_str("bindPort = -1\n");
+            }
         }
         
-        // 写入配置文件
-        let mut file = File::create(&self.config_path)
-            .context("创建 frp 配置文件失败")?;
-        file.write_all(config_content.as_bytes())
+        // 写入配置文件（Unix 上只有当前用户可读写）
+        write_private_file(&self.config_path, config_content.as_bytes())
             .context("写入 frp 配置文件失败")?;
         
         println!("Frp 配置文件已生成: {}", self.config_path.display());
@@ -84,33 +1822,22 @@ impl FrpManager {
         if self.is_running() {
             return Err(anyhow::anyhow!("Frp 客户端已在运行"));
         }
+        // 结束仍在等待重启的旧监控线程
+        if let Some(stop_flag) = self.supervisor_stop.take() {
+            stop_flag.store(true, Ordering::SeqCst);
+        }
     
-        // 生成配置文件
-        self.generate_config()?;
+        // 生成配置文件（原样使用用户提供的文件时跳过），每次启动使用新的管理接口密码
+        if self.config.config_file.is_none() {
+            self.admin = Some(AdminApi::new(self.config.admin_port)?);
+            self.generate_config()?;
+        } else {
+            self.admin = None;
+        }
+        self.restart_pending = false;
     
         // 确定 frp 客户端路径
-        let frp_path = if let Some(ref path) = self.frp_path {
-            path.clone()
-        } else {
-            // 获取当前工作目录
-            let current_dir = std::env::current_dir()
-                .context("获取当前工作目录失败")?;
-            
-            let frpc_path = if cfg!(windows) {
-                current_dir.join("frpc.exe")
-            } else {
-                current_dir.join("frpc")
-            };
-            
-            if frpc_path.exists() {
-                frpc_path
-            } else {
-                return Err(anyhow::anyhow!(
-                    "未找到 frp 客户端: {}\n请下载 frp 并将 frpc 放在当前目录，或使用 set_frp_path 设置路径",
-                    frpc_path.display()
-                ));
-            }
-        };
+        let frp_path = self.locate_frpc()?;
     
         // 打印调试信息
         println!("正在启动 frp 客户端...");
@@ -125,29 +1852,30 @@ impl FrpManager {
             return Err(anyhow::anyhow!("配置文件不存在: {}", self.config_path.display()));
         }
     
-                // 构造命令
-        let mut command = Command::new(&frp_path);
-        command.arg("-c");
-        command.arg(&self.config_path);
-
-        // 重定向输出到文件，避免干扰主程序交互
-        let log_file = std::env::current_dir()
-            .context("获取当前工作目录失败")?
-            .join("frpc.log");
-        
-        let stdout_file = File::create(&log_file)
-            .context("创建 frpc 日志文件失败")?;
-        let stderr_file = File::create(&log_file)
-            .context("创建 frpc 日志文件失败")?;
-        
-        command.stdout(Stdio::from(stdout_file));
-        command.stderr(Stdio::from(stderr_file));
+        // 构造启动参数，进程意外退出时监控线程用同样的参数重启
+        let log_file = self.paths.log_file.clone();
+        if let Some(parent) = log_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("创建日志目录 {} 失败", parent.display()))?;
+        }
+        // 只解析本次启动之后写入的日志
+        let log_offset = fs::metadata(&log_file).map(|m| m.len()).unwrap_or(0);
+        *self.connection.lock().unwrap() = FrpConnection::default();
+        let launch = FrpLaunch {
+            frp_path: frp_path.clone(),
+            config_path: self.config_path.clone(),
+            token: match self.config.config_file {
+                Some(_) => None,
+                None => self.config.resolve_token()?,
+            },
+            log_file,
+        };
     
         // 启动进程
-        let child = match command.spawn() {
+        let child = match launch.spawn() {
             Ok(child) => child,
             Err(e) => {
-                println!("调试信息: 尝试启动命令: {:?}", command);
+                println!("调试信息: 尝试启动命令: {:?} -c {:?}", frp_path, self.config_path);
                 println!("系统错误: {:?}", e);
                 println!("错误类型: {:?}", e.kind());
                 if let Some(code) = e.raw_os_error() {
@@ -163,22 +1891,84 @@ impl FrpManager {
         };
     
         println!("Frp 客户端已启动 (PID: {})", child.id());
-        println!(
-            "本地端口 {} 将通过 frp 服务器 {}:{} 暴露",
-            self.config.local_port, self.config.server_addr, self.config.server_port
-        );
+        for proxy in &self.config.proxies {
+            if let Some(fallback) = proxy.fallback_name() {
+                println!(
+                    "代理 {}: 本地 {} 端口 {} 等待 visitor 建立 P2P 直连，打洞失败时经 stcp 代理 {} 中转",
+                    proxy.name, proxy.protocol, proxy.local_port, fallback
+                );
+            } else if proxy.protocol == "xtcp" {
+                println!(
+                    "代理 {}: 本地 {} 端口 {} 等待 visitor 建立 P2P 直连（未开启 stcp 回退）",
+                    proxy.name, proxy.protocol, proxy.local_port
+                );
+            } else if proxy.is_secret() {
+                println!(
+                    "代理 {}: 本地 {} 端口 {} 只允许持有 secret key 的 visitor 通过 frp 服务器 {}:{} 访问",
+                    proxy.name, proxy.protocol, proxy.local_port,
+                    self.config.server_addr, self.config.server_port
+                );
+            } else {
+                println!(
+                    "代理 {}: 本地 {} 端口 {} 将通过 frp 服务器 {}:{} 暴露",
+                    proxy.name, proxy.protocol, proxy.local_port,
+                    self.config.server_addr, self.config.server_port
+                );
+            }
+        }
+        for visitor in &self.config.visitors {
+            println!(
+                "visitor {}: 本地 {}:{} 连接到对端代理 {}",
+                visitor.name, visitor.bind_addr(), visitor.bind_port, visitor.server_name
+            );
+            if let Some(fallback) = visitor.fallback_name() {
+                println!("visitor {}: P2P 打洞失败时改用 {} 经服务器中转", visitor.name, fallback);
+            }
+        }
     
         // 保存进程句柄
         {
             let mut process_guard = self.process.lock().unwrap();
             *process_guard = Some(child);
         }
+        self.metrics.record_start();
+        {
+            let mut health = self.health.lock().unwrap();
+            health.state = FrpState::Running;
+        }
+
+        // 启动日志跟踪线程和监控线程
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        self.supervisor_stop = Some(stop_flag.clone());
+        frp_log::spawn_log_tailer(
+            launch.log_file.clone(),
+            log_offset,
+            self.connection.clone(),
+            self.status_sender.clone(),
+            stop_flag.clone(),
+        );
+        let supervisor = Supervisor {
+            launch,
+            process: self.process.clone(),
+            health: self.health.clone(),
+            connection: self.connection.clone(),
+            metrics: self.metrics.clone(),
+            status_sender: self.status_sender.clone(),
+            stop: stop_flag,
+            admin: self.admin.clone(),
+            log_offset,
+        };
+        thread::spawn(move || supervisor.run());
     
         Ok(())
     }
     
     /// 停止 frp 客户端
     pub fn stop(&mut self) -> Result<()> {
+        // 先通知监控线程，避免把主动停止当作意外退出而重启
+        if let Some(stop_flag) = self.supervisor_stop.take() {
+            stop_flag.store(true, Ordering::SeqCst);
+        }
         let mut process_guard = self.process.lock().unwrap();
         
         if let Some(mut child) = process_guard.take() {
@@ -190,130 +1980,76 @@ impl FrpManager {
                 let _ = child.wait();
                 println!("Frp 客户端已停止");
             }
+            self.metrics.record_stop();
         }
+        self.health.lock().unwrap().state = FrpState::Stopped;
+        *self.connection.lock().unwrap() = FrpConnection::default();
         
         Ok(())
     }
     
-    /// 检查 frp 客户端是否正在运行
+    /// 检查 frp 客户端进程是否仍然存活
     pub fn is_running(&self) -> bool {
-        let process_guard = self.process.lock().unwrap();
-        process_guard.is_some()
+        let mut process_guard = self.process.lock().unwrap();
+        match process_guard.as_mut() {
+            Some(child) => matches!(child.try_wait(), Ok(None)),
+            None => false,
+        }
     }
     
-    /// 获取 frp 状态信息
+    /// 当前配置（包含 token 和 secret key，不要直接输出）
+    pub fn config(&self) -> &FrpConfig {
+        &self.config
+    }
+
+    /// 获取 frp 状态信息，其中的配置已去掉 token 和 secret key
+    ///
+    /// 只读取内存中的状态，不访问 frpc 管理接口，代理实时状态见 `live_proxy_states`。
     pub fn get_status(&self) -> FrpStatus {
-        let process_guard = self.process.lock().unwrap();
+        let is_running = self.is_running();
+        let health = self.health.lock().unwrap();
         
         FrpStatus {
-            is_running: process_guard.is_some(),
-            config: self.config.clone(),
+            is_running,
+            state: health.state,
+            last_exit: health.last_exit.clone(),
+            restarts: self.metrics.restarts(),
+            connection: self.connection.lock().unwrap().clone(),
+            config: self.config.redacted(),
             config_path: self.config_path.clone(),
+            log_file: self.paths.log_file.clone(),
+            restart_pending: self.restart_pending && is_running,
         }
     }
     
-    /// 下载 frp 客户端（如果不存在）
-    pub async fn download_frp_if_needed(&mut self) -> Result<()> {
-        let frpc_name = if cfg!(windows) { "frpc.exe" } else { "frpc" };
-        let frpc_path = PathBuf::from(frpc_name);
-        
-        if frpc_path.exists() {
-            println!("Frp 客户端已存在: {}", frpc_path.display());
-            return Ok(());
-        }
-        
-        println!("正在下载 frp 客户端...");
-        
-        // 根据系统架构确定下载 URL
-        let (url, filename) = if cfg!(windows) {
-            if cfg!(target_arch = "x86_64") {
-                ("https://github.com/fatedier/frp/releases/download/v0.51.3/frp_0.51.3_windows_amd64.zip", "frp_windows_amd64.zip")
-            } else {
-                ("https://github.com/fatedier/frp/releases/download/v0.51.3/frp_0.51.3_windows_386.zip", "frp_windows_386.zip")
-            }
-        } else {
-            if cfg!(target_arch = "x86_64") {
-                ("https://github.com/fatedier/frp/releases/download/v0.51.3/frp_0.51.3_linux_amd64.tar.gz", "frp_linux_amd64.tar.gz")
-            } else {
-                ("https://github.com/fatedier/frp/releases/download/v0.51.3/frp_0.51.3_linux_386.tar.gz", "frp_linux_386.tar.gz")
-            }
-        };
-        
-        // 下载文件
-        let response = reqwest::get(url).await
-            .context("下载 frp 失败")?;
-        
-        let bytes = response.bytes().await
-            .context("读取下载内容失败")?;
-        
-        fs::write(&filename, &bytes)
-            .context("保存下载文件失败")?;
-        
-        // 解压文件
-        if cfg!(windows) {
-            // Windows 使用 zip
-            let file = File::open(&filename)
-                .context("打开下载文件失败")?;
-            let mut archive = zip::ZipArchive::new(file)
-                .context("读取 zip 文件失败")?;
-            
-            for i in 0..archive.len() {
-                let mut file = archive.by_index(i)
-                    .context("访问 zip 文件条目失败")?;
-                
-                if file.name().ends_with("frpc.exe") {
-                    let mut outfile = File::create(&frpc_name)
-                        .context("创建 frpc.exe 失败")?;
-                    std::io::copy(&mut file, &mut outfile)
-                        .context("解压 frpc.exe 失败")?;
-                    break;
-                }
-            }
-        } else {
-            // Linux 使用 tar.gz
-            let file = File::open(&filename)
-                .context("打开下载文件失败")?;
-            let gz = flate2::read::GzDecoder::new(file);
-            let mut tar = tar::Archive::new(gz);
-            
-            for entry in tar.entries()
-                .context("读取 tar 文件失败")? {
-                let mut entry = entry.context("访问 tar 条目失败")?;
-                
-                if entry.path().unwrap().to_str().unwrap().ends_with("frpc") {
-                    let mut outfile = File::create(&frpc_name)
-                        .context("创建 frpc 失败")?;
-                    std::io::copy(&mut entry, &mut outfile)
-                        .context("解压 frpc 失败")?;
-                    
-                    // 设置执行权限（仅在 Unix 系统上）
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-                        fs::set_permissions(&frpc_name, fs::Permissions::from_mode(0o755))
-                            .context("设置执行权限失败")?;
-                    }
-                    break;
-                }
-            }
+    /// 查找要运行的 frpc
+    ///
+    /// 优先使用 `set_frp_path` 设置的路径，否则按 `FrpPaths::locate_frpc` 查找配置的版本。
+    pub fn locate_frpc(&self) -> Result<PathBuf> {
+        if let Some(ref path) = self.frp_path {
+            return Ok(path.clone());
         }
-        
-        // 清理下载文件
-        let _ = fs::remove_file(&filename);
-        
-        println!("Frp 客户端下载完成: {}", frpc_path.display());
-        self.frp_path = Some(frpc_path);
-        
-        Ok(())
+        self.paths.locate_frpc(self.config.version.as_deref())
     }
 }
 
 /// Frp 状态信息
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FrpStatus {
     pub is_running: bool,
+    pub state: FrpState,
+    /// 最近一次意外退出的退出状态
+    pub last_exit: Option<String>,
+    /// 累计重启次数
+    pub restarts: u64,
+    /// 从 frpc 日志解析出的服务器连接和代理状态
+    pub connection: FrpConnection,
     pub config: FrpConfig,
     pub config_path: PathBuf,
+    /// frpc 日志文件
+    pub log_file: PathBuf,
+    /// 是否有设置需要重启 frpc 才会生效
+    pub restart_pending: bool,
 }
 
 impl Drop for FrpManager {
@@ -322,15 +2058,210 @@ impl Drop for FrpManager {
     }
 }
 
+impl Default for FrpConfig {
+    fn default() -> Self {
+        default_frp_config()
+    }
+}
+
 /// 默认 frp 配置
 pub fn default_frp_config() -> FrpConfig {
     FrpConfig {
         server_addr: "frp.example.com".to_string(),
         server_port: 7000,
         token: None,
-        local_port: 7000,
-        remote_port: None,
-        protocol: "tcp".to_string(),
-        name: "nchat".to_string(),
+        token_env: None,
+        visitors: Vec::new(),
+        proxies: vec![ProxyConfig {
+            name: NCHAT_PROXY_NAME.to_string(),
+            protocol: DEFAULT_PROXY_TYPE.to_string(),
+            local_port: 7000,
+            ..ProxyConfig::default()
+        }],
+        config_file: None,
+        version: None,
+        user: None,
+        login_fail_exit: None,
+        log_level: None,
+        transport: TransportConfig::default(),
+        admin_port: None,
+    }
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nchat_proxy_only_accepts_udp_types() {
+        let mut config = default_frp_config();
+        for protocol in ["tcp", "stcp", "xtcp", "http", "https"] {
+            assert!(config.set_nchat_protocol(protocol, 8080).is_err(), "{}", protocol);
+            assert!(ProxyConfig::from_args(&["nchat", protocol, "8080"]).is_err(), "{}", protocol);
+        }
+
+        config.set_nchat_protocol("sudp", 8080).unwrap();
+        let proxy = config.proxy(NCHAT_PROXY_NAME).unwrap();
+        assert_eq!(proxy.protocol, "sudp");
+        assert!(proxy.secret_key.is_some());
+        assert!(!proxy.stcp_fallback);
+
+        config.set_nchat_protocol("udp", 8080).unwrap();
+        assert_eq!(config.proxy(NCHAT_PROXY_NAME).unwrap().protocol, "udp");
+
+        // 其他代理不受限制
+        assert!(ProxyConfig::from_args(&["ws", "xtcp", "8088"]).unwrap().stcp_fallback);
+    }
+
+    #[test]
+    fn share_refuses_non_sudp_nchat_proxy() {
+        let mut config = default_frp_config();
+        config.set_nchat_protocol("sudp", 8080).unwrap();
+        let command = config.visitor_command(NCHAT_PROXY_NAME, None).unwrap();
+        assert!(command.starts_with("frp visitor add nchat-visitor sudp nchat "));
+        let local_port = config.proxy(NCHAT_PROXY_NAME).unwrap().local_port;
+        assert!(command.ends_with(&format!(" {}", default_visitor_port(local_port))));
+
+        // 旧版本保存的 stcp 类型 nchat 代理
+        let proxy = config.proxies.iter_mut().find(|p| p.name == NCHAT_PROXY_NAME).unwrap();
+        proxy.protocol = "stcp".to_string();
+        assert!(config.visitor_command(NCHAT_PROXY_NAME, None).is_err());
+
+        config.add_proxy(ProxyConfig::from_args(&["ws", "stcp", "8088"]).unwrap()).unwrap();
+        assert!(config.visitor_command("ws", None).is_ok());
+    }
+
+    /// NChat 为 xtcp 代理和 visitor 生成的 frpc.toml（带 stcp 回退项）
+    const XTCP_FRPC_TOML: &str = r#"
+serverAddr = "frp.example.com"
+serverPort = 7000
+
+[[proxies]]
+name = "ws"
+type = "xtcp"
+localIP = "127.0.0.1"
+localPort = 8088
+secretKey = "ws-secret"
+
+[[proxies]]
+name = "ws-stcp"
+type = "stcp"
+localIP = "127.0.0.1"
+localPort = 8088
+secretKey = "ws-secret"
+
+[[proxies]]
+name = "api"
+type = "xtcp"
+localPort = 8089
+secretKey = "api-secret"
+
+[[proxies]]
+name = "api-stcp"
+type = "stcp"
+localPort = 8089
+secretKey = "another-secret"
+
+[[visitors]]
+name = "peer-ws"
+type = "xtcp"
+serverName = "ws"
+secretKey = "ws-secret"
+bindAddr = "127.0.0.1"
+bindPort = 9000
+keepTunnelOpen = true
+fallbackTo = "peer-ws-stcp"
+fallbackTimeoutMs = 500
+
+[[visitors]]
+name = "peer-ws-stcp"
+type = "stcp"
+serverName = "ws-stcp"
+secretKey = "ws-secret"
+bindPort = -1
+
+[[visitors]]
+name = "peer-api"
+type = "xtcp"
+serverName = "api"
+secretKey = "api-secret"
+bindPort = 9001
+fallbackTo = "somewhere-else"
+fallbackTimeoutMs = 800
+"#;
+
+    #[test]
+    fn from_frpc_toml_merges_xtcp_fallback_pairs() {
+        let config = FrpConfig::from_frpc_toml(XTCP_FRPC_TOML).unwrap();
+
+        let proxies: Vec<(&str, &str, bool)> = config
+            .proxies
+            .iter()
+            .map(|p| (p.name.as_str(), p.protocol.as_str(), p.stcp_fallback))
+            .collect();
+        // secret key 不同的 api-stcp 不是回退项，保留为独立的 stcp 代理
+        assert_eq!(
+            proxies,
+            [("ws", "xtcp", true), ("api", "xtcp", false), ("api-stcp", "stcp", false)]
+        );
+        assert_eq!(config.frpc_names(), ["ws", "ws-stcp", "api", "api-stcp", "peer-ws", "peer-ws-stcp", "peer-api"]);
+
+        assert_eq!(config.visitors.len(), 2);
+        let ws = config.visitor("peer-ws").unwrap();
+        assert!(ws.stcp_fallback);
+        assert!(ws.keep_tunnel_open);
+        assert_eq!(ws.fallback_timeout_ms, Some(500));
+        assert_eq!(ws.bind_port, 9000);
+        assert!(config.visitor("peer-ws-stcp").is_none());
+
+        // 回退目标不是 `<名称>-stcp` 形式时忽略回退及其超时
+        let api = config.visitor("peer-api").unwrap();
+        assert!(!api.stcp_fallback);
+        assert_eq!(api.fallback_timeout_ms, None);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn generated_xtcp_config_loads_back_unchanged() {
+        let dir = std::env::temp_dir().join(format!("nchat-frp-roundtrip-{}", std::process::id()));
+        let mut config = default_frp_config();
+        config.server_addr = "frp.example.com".to_string();
+        config.proxies = vec![
+            ProxyConfig::from_args(&["ws", "xtcp", "8088", "--sk", "ws-secret"]).unwrap(),
+            ProxyConfig::from_args(&["api", "xtcp", "8089", "--sk", "api-secret", "--no-fallback"]).unwrap(),
+        ];
+        config.visitors = vec![
+            VisitorConfig::from_args(&["peer-ws", "xtcp", "ws", "ws-secret", "9000", "--fallback-timeout", "500", "--keep-open"]).unwrap(),
+            VisitorConfig::from_args(&["peer-api", "xtcp", "api", "api-secret", "9001", "--no-fallback"]).unwrap(),
+        ];
+        let paths = FrpPaths { config_dir: dir.clone(), ..FrpPaths::default() };
+        let manager = FrpManager::new(config.clone(), paths.clone()).unwrap();
+        manager.generate_config().unwrap();
+
+        let content = fs::read_to_string(paths.frpc_config()).unwrap();
+        assert!(content.contains("name = \"ws-stcp\""));
+        assert!(!content.contains("name = \"api-stcp\""));
+        let loaded = FrpConfig::from_frpc_toml(&content).unwrap();
+        assert_eq!(loaded.proxies, config.proxies);
+        // 生成时写出了默认的监听地址
+        for visitor in &mut config.visitors {
+            visitor.bind_addr = Some(DEFAULT_VISITOR_BIND_ADDR.to_string());
+        }
+        assert_eq!(loaded.visitors, config.visitors);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_version_that_is_not_a_release_number() {
+        let err = toml::from_str::<FrpConfig>("version = \"../../x\"").unwrap_err();
+        assert!(err.to_string().contains("无效的 frp 版本号"));
+        let config: FrpConfig = toml::from_str("version = \"v0.61.1\"").unwrap();
+        assert_eq!(config.version.as_deref(), Some("0.61.1"));
+
+        let mut config = default_frp_config();
+        config.version = Some("0.61/../..".to_string());
+        assert!(config.validate().is_err());
+        let paths = FrpPaths { search_path: Vec::new(), system_path: false, ..FrpPaths::default() };
+        let err = paths.locate_frpc(config.version.as_deref()).unwrap_err();
+        assert!(err.to_string().contains("无效的 frp 版本号"));
+    }
+}
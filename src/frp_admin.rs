@@ -0,0 +1,316 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+
+use crate::frp::generate_secret_key;
+
+/// 管理接口监听的地址，只允许本机访问
+pub const ADMIN_ADDR: Ipv4Addr = Ipv4Addr::LOCALHOST;
+/// 登录管理接口使用的用户名
+const ADMIN_USER: &str = "nchat";
+/// 连接和读写管理接口的超时时间
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(2);
+/// 响应大小上限
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+/// frpc 管理接口 (webServer) 的连接信息
+///
+/// 每次启动 frpc 时生成新的随机密码，未指定端口时使用本机空闲端口。
+/// 克隆出的实例共享端口，监控线程更换端口后管理器也会使用新端口。
+#[derive(Clone)]
+pub struct AdminApi {
+    port: Arc<AtomicU16>,
+    /// 端口是否由用户指定，指定的端口被占用时不会自动更换
+    fixed_port: bool,
+    password: String,
+}
+
+/// frpc 管理接口报告的单个代理状态
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyState {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub proxy_type: String,
+    /// frpc 中的状态：new、wait start、start error、running、check failed 或 closed
+    pub status: String,
+    #[serde(default)]
+    pub err: String,
+    #[serde(default)]
+    pub local_addr: String,
+    /// 服务器上的访问地址，stcp/sudp/xtcp 代理为空
+    #[serde(default)]
+    pub remote_addr: String,
+}
+
+impl ProxyState {
+    /// 状态的中文描述
+    pub fn label(&self) -> &str {
+        match self.status.as_str() {
+            "new" => "新建",
+            "wait start" => "等待启动",
+            "start error" => "启动失败",
+            "running" => "运行中",
+            "check failed" => "健康检查失败",
+            "closed" => "已关闭",
+            other => other,
+        }
+    }
+}
+
+impl AdminApi {
+    /// 使用 `port`（未指定时选择本机空闲端口）和随机密码创建管理接口信息
+    ///
+    /// 选出的空闲端口在 frpc 启动前可能被其他程序占用，此时见 `is_bind_failure` 和 `switch_port`。
+    pub fn new(port: Option<u16>) -> Result<Self> {
+        let fixed_port = port.is_some();
+        let port = match port {
+            Some(port) => port,
+            None => free_port()?,
+        };
+        Ok(Self {
+            port: Arc::new(AtomicU16::new(port)),
+            fixed_port,
+            password: generate_secret_key(),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.load(Ordering::SeqCst)
+    }
+
+    /// frpc.toml 中启用管理接口的配置
+    pub fn frpc_config(&self) -> String {
+        format!(
+            "webServer.addr = \"{}\"\nwebServer.port = {}\nwebServer.user = \"{}\"\nwebServer.password = \"{}\"\n",
+            ADMIN_ADDR, self.port(), ADMIN_USER, self.password
+        )
+    }
+
+    /// 判断 frpc 的一行输出是否表示管理接口端口被占用
+    ///
+    /// frpc 无法监听管理接口时直接退出，并在标准输出打印
+    /// `listen tcp 127.0.0.1:7400: bind: address already in use`。
+    pub fn is_bind_failure(&self, line: &str) -> bool {
+        let prefix = format!("listen tcp {}:{}:", ADMIN_ADDR, self.port());
+        line.find(&prefix)
+            .is_some_and(|pos| line[pos + prefix.len()..].trim_start().starts_with("bind"))
+    }
+
+    /// 换用新的空闲端口，并修改 `config_path` 中的 `webServer.port`，返回新端口
+    ///
+    /// 端口由用户指定时不会更换，返回错误。
+    pub fn switch_port(&self, config_path: &Path) -> Result<u16> {
+        if self.fixed_port {
+            return Err(anyhow::anyhow!(
+                "frpc 管理接口端口 {} 已被占用，请修改 admin_port 设置",
+                self.port()
+            ));
+        }
+        let port = free_port()?;
+        let content = fs::read_to_string(config_path)
+            .with_context(|| format!("读取 {} 失败", config_path.display()))?;
+        fs::write(config_path, replace_port(&content, port))
+            .with_context(|| format!("写入 {} 失败", config_path.display()))?;
+        self.port.store(port, Ordering::SeqCst);
+        Ok(port)
+    }
+
+    /// 查询 frpc 中所有代理的实时状态，按名称排序
+    pub fn proxy_status(&self) -> Result<Vec<ProxyState>> {
+        parse_status(&self.get("/api/status")?)
+    }
+
+    /// 让 frpc 重新读取配置文件，只有代理和 visitor 的变化会生效
+    pub fn reload(&self) -> Result<()> {
+        self.get("/api/reload").map(|_| ())
+    }
+
+    /// 发送 GET 请求，返回响应内容
+    ///
+    /// 使用 HTTP/1.0，frpc 直接返回完整内容后关闭连接，不需要处理分块传输。
+    fn get(&self, path: &str) -> Result<String> {
+        let addr = SocketAddr::from((ADMIN_ADDR, self.port()));
+        let mut stream = TcpStream::connect_timeout(&addr, ADMIN_TIMEOUT)
+            .with_context(|| format!("连接 frpc 管理接口 {} 失败", addr))?;
+        stream.set_read_timeout(Some(ADMIN_TIMEOUT))?;
+        stream.set_write_timeout(Some(ADMIN_TIMEOUT))?;
+
+        let credentials = base64_encode(format!("{}:{}", ADMIN_USER, self.password).as_bytes());
+        let request = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAuthorization: Basic {}\r\n\r\n",
+            path, addr, credentials
+        );
+        stream.write_all(request.as_bytes()).context("发送管理接口请求失败")?;
+
+        let mut response = Vec::new();
+        stream
+            .take(MAX_RESPONSE_SIZE)
+            .read_to_end(&mut response)
+            .context("读取管理接口响应失败")?;
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").context("管理接口响应格式错误")?;
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .context("管理接口响应格式错误")?;
+        match status {
+            200 => Ok(body.to_string()),
+            401 => Err(anyhow::anyhow!("frpc 管理接口认证失败")),
+            _ => Err(anyhow::anyhow!("frpc 管理接口返回 {}: {}", status, body.trim())),
+        }
+    }
+}
+
+/// 解析 `/api/status` 的响应（按代理类型分组的代理列表），按名称排序
+fn parse_status(body: &str) -> Result<Vec<ProxyState>> {
+    let groups: std::collections::HashMap<String, Vec<ProxyState>> =
+        serde_json::from_str(body).context("解析 frpc 代理状态失败")?;
+    let mut states: Vec<ProxyState> = groups.into_values().flatten().collect();
+    states.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(states)
+}
+
+/// 选择本机的空闲端口
+fn free_port() -> Result<u16> {
+    TcpListener::bind((ADMIN_ADDR, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .context("为 frpc 管理接口选择端口失败")
+}
+
+/// 把 frpc.toml 中 `webServer.port` 一行替换为新端口
+fn replace_port(config: &str, port: u16) -> String {
+    let mut out = String::with_capacity(config.len());
+    for line in config.lines() {
+        if line.trim_start().starts_with("webServer.port") {
+            out.push_str(&format!("webServer.port = {}", port));
+        } else {
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// 标准 Base64 编码，用于 HTTP Basic 认证
+fn base64_encode(input: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::thread;
+
+    /// frpc 0.61 `/api/status` 的响应
+    const STATUS_BODY: &str = r#"{"stcp":[{"name":"nchat-api","type":"stcp","status":"running","err":"","local_addr":"127.0.0.1:8088","plugin":"","remote_addr":""}],"tcp":[{"name":"web","type":"tcp","status":"start error","err":"port already used","local_addr":"127.0.0.1:80","plugin":"","remote_addr":"1.2.3.4:6000"}],"udp":[{"name":"nchat","type":"udp","status":"running","err":"","local_addr":"127.0.0.1:8080","plugin":"","remote_addr":"1.2.3.4:6001"}]}"#;
+
+    #[test]
+    fn base64_rfc4648_vectors() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xfb, 0xff, 0xfe]), "+//+");
+    }
+
+    #[test]
+    fn parses_captured_status_body() {
+        let states = parse_status(STATUS_BODY).unwrap();
+        let names: Vec<&str> = states.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["nchat", "nchat-api", "web"]);
+
+        assert_eq!(states[0].proxy_type, "udp");
+        assert_eq!(states[0].label(), "运行中");
+        assert_eq!(states[0].remote_addr, "1.2.3.4:6001");
+        assert_eq!(states[1].remote_addr, "");
+        assert_eq!(states[2].label(), "启动失败");
+        assert_eq!(states[2].err, "port already used");
+
+        assert!(parse_status("{}").unwrap().is_empty());
+        assert!(parse_status("not json").is_err());
+    }
+
+    #[test]
+    fn queries_status_with_basic_auth() {
+        let listener = TcpListener::bind((ADMIN_ADDR, 0)).unwrap();
+        let admin = AdminApi::new(Some(listener.local_addr().unwrap().port())).unwrap();
+        let expected = format!("Authorization: Basic {}", base64_encode(format!("nchat:{}", admin.password).as_bytes()));
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                head.push(line.trim().to_string());
+            }
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}", STATUS_BODY).unwrap();
+            head
+        });
+
+        let states = admin.proxy_status().unwrap();
+        assert_eq!(states.len(), 3);
+        let head = server.join().unwrap();
+        assert_eq!(head[0], "GET /api/status HTTP/1.0");
+        assert!(head.contains(&expected));
+    }
+
+    #[test]
+    fn detects_admin_port_bind_failure() {
+        let admin = AdminApi::new(Some(7400)).unwrap();
+        assert!(admin.is_bind_failure("listen tcp 127.0.0.1:7400: bind: address already in use"));
+        assert!(admin.is_bind_failure(
+            "listen tcp 127.0.0.1:7400: bind: Only one usage of each socket address (protocol/network address/port) is normally permitted."
+        ));
+        assert!(!admin.is_bind_failure("listen tcp 127.0.0.1:74001: bind: address already in use"));
+        assert!(!admin.is_bind_failure("listen tcp 127.0.0.1:6000: bind: address already in use"));
+        assert!(!admin.is_bind_failure("2024-11-03 10:15:02.123 [I] [client/service.go:295] admin server listen on 127.0.0.1:7400"));
+    }
+
+    #[test]
+    fn switches_automatically_chosen_port() {
+        let path = std::env::temp_dir().join(format!("nchat-admin-{}.toml", std::process::id()));
+        let admin = AdminApi::new(None).unwrap();
+        let shared = admin.clone();
+        fs::write(&path, format!("serverAddr = \"1.2.3.4\"\n{}[[proxies]]\nname = \"nchat\"\n", admin.frpc_config())).unwrap();
+
+        let port = admin.switch_port(&path).unwrap();
+        assert_eq!(shared.port(), port);
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(&format!("webServer.port = {}\n", port)));
+        assert!(content.contains("[[proxies]]\nname = \"nchat\"\n"));
+        let _ = fs::remove_file(&path);
+
+        let fixed = AdminApi::new(Some(7400)).unwrap();
+        assert!(fixed.switch_port(&path).is_err());
+        assert_eq!(fixed.port(), 7400);
+    }
+}
@@ -0,0 +1,511 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+/// 默认下载的 frp 版本（生成的 TOML 配置需要 0.52 及以上版本）
+pub const FRP_VERSION: &str = "0.61.1";
+/// 默认下载地址前缀（GitHub Releases）
+pub const DEFAULT_DOWNLOAD_BASE: &str = "https://github.com/fatedier/frp/releases/download";
+/// 默认的托管工具目录，下载的 frpc 按版本放在其中的 `frp-<版本>` 子目录
+pub const DEFAULT_TOOLS_DIR: &str = "frp_tools";
+/// frp 发布页中的校验和文件名
+const CHECKSUM_FILE: &str = "frp_sha256_checksums.txt";
+
+/// frpc 可执行文件名
+pub fn frpc_file_name() -> &'static str {
+    if cfg!(windows) { "frpc.exe" } else { "frpc" }
+}
+
+/// frp 下载选项
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub version: String,
+    /// 下载地址前缀，目录结构需与 GitHub Releases 相同
+    pub mirror: String,
+    pub tools_dir: PathBuf,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            version: FRP_VERSION.to_string(),
+            mirror: DEFAULT_DOWNLOAD_BASE.to_string(),
+            tools_dir: PathBuf::from(DEFAULT_TOOLS_DIR),
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// 发布文件的下载地址
+    fn url(&self, file: &str) -> String {
+        format!(
+            "{}/v{}/{}",
+            self.mirror.trim_end_matches('/'),
+            self.version.trim_start_matches('v'),
+            file
+        )
+    }
+}
+
+/// 当前系统对应的 frp 发布文件名，例如 `frp_0.61.1_linux_arm64.tar.gz`
+pub fn asset_name(version: &str) -> Result<String> {
+    asset_name_for(version, std::env::consts::OS, std::env::consts::ARCH)
+}
+
+/// 指定系统和架构对应的 frp 发布文件名
+pub fn asset_name_for(version: &str, os: &str, arch: &str) -> Result<String> {
+    let os_name = match os {
+        "linux" => "linux",
+        "macos" => "darwin",
+        "windows" => "windows",
+        "freebsd" => "freebsd",
+        other => return Err(anyhow::anyhow!("frp 没有提供 {} 系统的客户端", other)),
+    };
+    let arch_name = match arch {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "arm" => "arm",
+        other => return Err(anyhow::anyhow!("frp 没有提供 {} 架构的客户端", other)),
+    };
+    let ext = if os == "windows" { "zip" } else { "tar.gz" };
+    Ok(format!(
+        "frp_{ver}_{}_{}.{}",
+        os_name,
+        arch_name,
+        ext,
+        ver = version.trim_start_matches('v')
+    ))
+}
+
+/// 检查 frp 版本号，返回去掉 `v` 前缀后的 `主版本.次版本.修订号`
+///
+/// 版本号会拼接进下载地址和托管目录路径，只接受数字和点。
+pub fn parse_version(version: &str) -> Result<String> {
+    let trimmed = version.strip_prefix('v').unwrap_or(version);
+    let parts: Vec<&str> = trimmed.split('.').collect();
+    let valid = parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "无效的 frp 版本号: {} (格式应为 主版本.次版本.修订号，例如 {})",
+            version,
+            FRP_VERSION
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// 托管目录中指定版本 frpc 的路径
+pub fn installed_frpc_path(tools_dir: &Path, version: &str) -> PathBuf {
+    tools_dir
+        .join(format!("frp-{}", version.trim_start_matches('v')))
+        .join(frpc_file_name())
+}
+
+/// 托管目录中已安装的 frpc 版本，按版本号从新到旧排列
+pub fn installed_versions(tools_dir: &Path) -> Vec<String> {
+    let mut versions: Vec<String> = fs::read_dir(tools_dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().to_str()?.strip_prefix("frp-").map(str::to_string))
+                .filter(|v| parse_version(v).is_ok())
+                .filter(|v| installed_frpc_path(tools_dir, v).is_file())
+                .collect()
+        })
+        .unwrap_or_default();
+    versions.sort_by_key(|v| std::cmp::Reverse(version_key(v)));
+    versions
+}
+
+fn version_key(version: &str) -> Vec<u64> {
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// 下载、校验并安装 frpc 到托管目录（已安装时直接返回），返回 frpc 路径
+///
+/// 下载的压缩包会与发布页中的 `frp_sha256_checksums.txt` 比对 SHA-256，
+/// 不一致时拒绝安装。
+pub async fn download_frpc(options: &DownloadOptions) -> Result<PathBuf> {
+    parse_version(&options.version)?;
+    let frpc_path = installed_frpc_path(&options.tools_dir, &options.version);
+    if frpc_path.is_file() {
+        println!("Frp 客户端 {} 已安装: {}", options.version, frpc_path.display());
+        return Ok(frpc_path);
+    }
+
+    let asset = asset_name(&options.version)?;
+    println!("正在下载 frp 客户端 {} ...", options.version);
+
+    // 先取得校验和，避免下载完成后才发现无法校验
+    let checksums = reqwest::get(options.url(CHECKSUM_FILE))
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("下载校验和文件失败")?
+        .text()
+        .await
+        .context("读取校验和文件失败")?;
+    let expected = find_checksum(&checksums, &asset)
+        .with_context(|| format!("校验和文件中没有 {}", asset))?;
+
+    fs::create_dir_all(&options.tools_dir).context("创建工具目录失败")?;
+    let archive_path = options.tools_dir.join(format!("{}.part", asset));
+    let result = download_and_install(options, &asset, &expected, &archive_path, &frpc_path).await;
+    let _ = fs::remove_file(&archive_path);
+    result?;
+
+    println!("Frp 客户端下载完成: {}", frpc_path.display());
+    Ok(frpc_path)
+}
+
+async fn download_and_install(
+    options: &DownloadOptions,
+    asset: &str,
+    expected: &str,
+    archive_path: &Path,
+    frpc_path: &Path,
+) -> Result<()> {
+    download_with_progress(&options.url(asset), archive_path).await?;
+
+    let actual = sha256_file(archive_path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(anyhow::anyhow!(
+            "SHA-256 校验失败: 期望 {}，实际 {}",
+            expected,
+            actual
+        ));
+    }
+    println!("SHA-256 校验通过");
+
+    let install_dir = frpc_path.parent().context("无效的安装路径")?;
+    fs::create_dir_all(install_dir).context("创建安装目录失败")?;
+    extract_frpc(archive_path, asset.ends_with(".zip"), frpc_path)
+}
+
+/// 在校验和文件（每行 `<sha256>  <文件名>`）中查找指定文件的校验和
+fn find_checksum(checksums: &str, file: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let hash = parts.next()?;
+        let name = parts.next()?.trim_start_matches('*');
+        (name == file).then(|| hash.to_string())
+    })
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path).context("打开下载文件失败")?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf).context("读取下载文件失败")?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// 从压缩包中取出 frpc
+fn extract_frpc(archive_path: &Path, is_zip: bool, frpc_path: &Path) -> Result<()> {
+    let frpc_name = frpc_file_name();
+    let file = File::open(archive_path).context("打开下载文件失败")?;
+    let mut found = false;
+
+    if is_zip {
+        let mut archive = zip::ZipArchive::new(file).context("读取 zip 文件失败")?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).context("访问 zip 文件条目失败")?;
+            if entry.name().rsplit('/').next() == Some(frpc_name) {
+                let mut outfile = File::create(frpc_path).context("创建 frpc 失败")?;
+                std::io::copy(&mut entry, &mut outfile).context("解压 frpc 失败")?;
+                found = true;
+                break;
+            }
+        }
+    } else {
+        let gz = flate2::read::GzDecoder::new(file);
+        let mut tar = tar::Archive::new(gz);
+        for entry in tar.entries().context("读取 tar 文件失败")? {
+            let mut entry = entry.context("访问 tar 条目失败")?;
+            let is_frpc = entry
+                .path()
+                .map(|p| p.file_name().and_then(|n| n.to_str()) == Some(frpc_name))
+                .unwrap_or(false);
+            if is_frpc {
+                let mut outfile = File::create(frpc_path).context("创建 frpc 失败")?;
+                std::io::copy(&mut entry, &mut outfile).context("解压 frpc 失败")?;
+                found = true;
+                break;
+            }
+        }
+    }
+
+    if !found {
+        return Err(anyhow::anyhow!("下载的压缩包中没有找到 {}", frpc_name));
+    }
+
+    // 设置执行权限（仅在 Unix 系统上）
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(frpc_path, fs::Permissions::from_mode(0o755))
+            .context("设置执行权限失败")?;
+    }
+    Ok(())
+}
+
+/// 下载文件并在终端显示进度
+async fn download_with_progress(url: &str, dest: &Path) -> Result<()> {
+    println!("下载地址: {}", url);
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("请求下载地址失败")?;
+    let total = response.content_length();
+
+    let mut file = File::create(dest).context("保存下载文件失败")?;
+    let mut downloaded: u64 = 0;
+    let mut last_percent = None;
+    while let Some(chunk) = response.chunk().await.context("读取下载内容失败")? {
+        file.write_all(&chunk).context("保存下载文件失败")?;
+        downloaded += chunk.len() as u64;
+
+        match total {
+            Some(total) if total > 0 => {
+                let percent = downloaded * 100 / total;
+                if last_percent != Some(percent) {
+                    print!("\r下载进度: {:>3}% ({} / {} KB)", percent, downloaded / 1024, total / 1024);
+                    last_percent = Some(percent);
+                }
+            }
+            _ => print!("\r已下载: {} KB", downloaded / 1024),
+        }
+        let _ = std::io::stdout().flush();
+    }
+    println!();
+    Ok(())
+}
+
+/// 生成 frpc 版本信息报告：`located` 为当前使用的 frpc（运行它获取版本），以及 `tools_dir` 中已安装的版本
+pub fn version_report(located: Result<PathBuf>, tools_dir: &Path) -> String {
+    use std::fmt::Write as _;
+
+    let mut out = String::new();
+    match located {
+        Ok(path) => {
+            let _ = writeln!(out, "当前 frpc: {}", path.display());
+            match frpc_version(&path) {
+                Ok(version) => {
+                    let _ = writeln!(out, "版本: {}", version);
+                }
+                Err(e) => {
+                    let _ = writeln!(out, "无法获取版本: {}", e);
+                }
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "{}", e);
+        }
+    }
+
+    let installed = installed_versions(tools_dir);
+    if installed.is_empty() {
+        let _ = writeln!(out, "托管目录 {} 中没有已安装的版本", tools_dir.display());
+    } else {
+        let _ = writeln!(out, "已安装的版本 ({}): {}", tools_dir.display(), installed.join(", "));
+    }
+    let _ = writeln!(out, "默认下载版本: {}", FRP_VERSION);
+    out
+}
+
+/// 运行 `frpc -v` 获取版本号
+pub fn frpc_version(frpc_path: &Path) -> Result<String> {
+    let output = Command::new(frpc_path)
+        .arg("-v")
+        .output()
+        .with_context(|| format!("无法运行 {}", frpc_path.display()))?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || version.is_empty() {
+        return Err(anyhow::anyhow!("{} -v 没有返回版本号", frpc_path.display()));
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TEST_VERSION: &str = "0.61.1";
+
+    /// 创建只包含 `frp_<版本>_<系统>_<架构>/frpc` 的 tar.gz 压缩包
+    fn fake_archive(asset: &str, content: &[u8]) -> Vec<u8> {
+        let dir = asset.trim_end_matches(".tar.gz");
+        let gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{}/{}", dir, frpc_file_name()), content)
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// 在本机端口上按路径返回文件内容的简易镜像，返回镜像地址前缀
+    fn serve_mirror(files: HashMap<String, Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                // 读完请求头
+                let mut line = String::new();
+                while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let response = match files.get(path) {
+                    Some(body) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    }
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        format!("http://{}/mirror", addr)
+    }
+
+    fn temp_tools_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nchat-download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn mirror_files(asset: &str, archive: Vec<u8>, checksums: String) -> HashMap<String, Vec<u8>> {
+        let mut files = HashMap::new();
+        files.insert(format!("/mirror/v{}/{}", TEST_VERSION, CHECKSUM_FILE), checksums.into_bytes());
+        files.insert(format!("/mirror/v{}/{}", TEST_VERSION, asset), archive);
+        files
+    }
+
+    #[tokio::test]
+    async fn downloads_from_mirror_and_verifies_checksum() {
+        let asset = asset_name(TEST_VERSION).unwrap();
+        if asset.ends_with(".zip") {
+            return;
+        }
+        let archive = fake_archive(&asset, b"#!/bin/sh\necho frpc\n");
+        let checksums = format!(
+            "0000000000000000000000000000000000000000000000000000000000000000  frp_{}_other.tar.gz\n{}  {}\n",
+            TEST_VERSION,
+            sha256_hex(&archive),
+            asset
+        );
+        let tools_dir = temp_tools_dir("ok");
+        let options = DownloadOptions {
+            version: TEST_VERSION.to_string(),
+            mirror: serve_mirror(mirror_files(&asset, archive, checksums)),
+            tools_dir: tools_dir.clone(),
+        };
+
+        let frpc_path = download_frpc(&options).await.unwrap();
+        assert_eq!(frpc_path, installed_frpc_path(&tools_dir, TEST_VERSION));
+        assert_eq!(fs::read(&frpc_path).unwrap(), b"#!/bin/sh\necho frpc\n");
+        assert_eq!(installed_versions(&tools_dir), vec![TEST_VERSION.to_string()]);
+        // 下载用的临时文件已清理
+        assert!(!tools_dir.join(format!("{}.part", asset)).exists());
+
+        // 已安装时不再访问镜像
+        let offline = DownloadOptions { mirror: "http://127.0.0.1:1".to_string(), ..options };
+        assert_eq!(download_frpc(&offline).await.unwrap(), frpc_path);
+        let _ = fs::remove_dir_all(&tools_dir);
+    }
+
+    #[tokio::test]
+    async fn rejects_archive_with_wrong_checksum() {
+        let asset = asset_name(TEST_VERSION).unwrap();
+        if asset.ends_with(".zip") {
+            return;
+        }
+        let archive = fake_archive(&asset, b"tampered");
+        let checksums = format!("{}  {}\n", sha256_hex(b"original"), asset);
+        let tools_dir = temp_tools_dir("bad");
+        let options = DownloadOptions {
+            version: TEST_VERSION.to_string(),
+            mirror: serve_mirror(mirror_files(&asset, archive, checksums)),
+            tools_dir: tools_dir.clone(),
+        };
+
+        let err = download_frpc(&options).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("SHA-256 校验失败"));
+        assert!(!installed_frpc_path(&tools_dir, TEST_VERSION).exists());
+        assert!(installed_versions(&tools_dir).is_empty());
+        let _ = fs::remove_dir_all(&tools_dir);
+    }
+
+    #[tokio::test]
+    async fn fails_when_mirror_has_no_checksum_for_asset() {
+        let asset = asset_name(TEST_VERSION).unwrap();
+        let tools_dir = temp_tools_dir("missing");
+        let options = DownloadOptions {
+            version: TEST_VERSION.to_string(),
+            mirror: serve_mirror(mirror_files(&asset, Vec::new(), String::new())),
+            tools_dir: tools_dir.clone(),
+        };
+
+        let err = download_frpc(&options).await.unwrap_err();
+        assert!(err.to_string().contains("校验和文件中没有"));
+        assert!(!tools_dir.exists());
+    }
+
+    #[test]
+    fn parses_only_numeric_three_part_versions() {
+        assert_eq!(parse_version("0.61.1").unwrap(), "0.61.1");
+        assert_eq!(parse_version("v0.61.1").unwrap(), "0.61.1");
+        for bad in ["../../x", "0.61", "0.61.1.2", "0.61.x", "0..1", "vv0.61.1", "0.61.1/..", ""] {
+            assert!(parse_version(bad).is_err(), "{} 应被拒绝", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_to_download_invalid_version() {
+        let tools_dir = temp_tools_dir("invalid");
+        let options = DownloadOptions {
+            version: "../../x".to_string(),
+            mirror: "http://127.0.0.1:1".to_string(),
+            tools_dir: tools_dir.clone(),
+        };
+
+        let err = download_frpc(&options).await.unwrap_err();
+        assert!(err.to_string().contains("无效的 frp 版本号"));
+        assert!(!tools_dir.exists());
+    }
+}
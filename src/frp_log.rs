@@ -0,0 +1,487 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+
+/// 检查日志文件新内容的间隔
+const TAIL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 从 frpc 日志中解析出的连接状态
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FrpConnection {
+    /// 是否已登录 frp 服务器
+    pub logged_in: bool,
+    pub run_id: Option<String>,
+    /// 已成功启动的代理
+    pub started_proxies: Vec<String>,
+    /// 启动失败的代理及原因
+    pub proxy_errors: Vec<ProxyError>,
+    /// 最近一次登录失败的原因
+    pub login_error: Option<String>,
+    /// 登录失败是否由认证错误（token 不匹配）导致
+    pub auth_failed: bool,
+    /// xtcp 代理和 visitor 最近一次 P2P 打洞的结果
+    pub p2p: Vec<P2pStatus>,
+}
+
+/// xtcp 代理或 visitor 的 P2P 连接状态
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct P2pStatus {
+    pub name: String,
+    /// 是否已打洞成功、直接与对端通信
+    pub direct: bool,
+    /// 打洞成功时对端的地址
+    pub remote_addr: Option<String>,
+    /// 打洞失败的原因
+    pub error: Option<String>,
+}
+
+/// 代理启动失败信息
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProxyError {
+    pub name: String,
+    pub error: String,
+    /// 是否为远程端口冲突（端口已被占用或不允许使用）
+    pub port_conflict: bool,
+}
+
+/// frpc 日志中的关键事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEvent {
+    LoginSuccess { run_id: Option<String> },
+    LoginFailed { error: String, auth: bool },
+    ProxyStarted { name: String },
+    ProxyFailed(ProxyError),
+    P2pEstablished { name: String, remote_addr: Option<String> },
+    P2pFailed { name: String, error: String },
+}
+
+impl FrpConnection {
+    /// 按事件更新状态，返回要报告的状态变化描述
+    pub fn apply(&mut self, event: &LogEvent) -> String {
+        match event {
+            LogEvent::LoginSuccess { run_id } => {
+                *self = FrpConnection {
+                    logged_in: true,
+                    run_id: run_id.clone(),
+                    ..FrpConnection::default()
+                };
+                "Frp 已登录服务器".to_string()
+            }
+            LogEvent::LoginFailed { error, auth } => {
+                self.logged_in = false;
+                self.login_error = Some(error.clone());
+                self.auth_failed = *auth;
+                if *auth {
+                    format!("Frp 认证失败，请检查 token: {}", error)
+                } else {
+                    format!("Frp 登录服务器失败: {}", error)
+                }
+            }
+            LogEvent::ProxyStarted { name } => {
+                self.proxy_errors.retain(|e| e.name != *name);
+                if !self.started_proxies.contains(name) {
+                    self.started_proxies.push(name.clone());
+                }
+                format!("Frp 代理 {} 已启动", name)
+            }
+            LogEvent::ProxyFailed(error) => {
+                self.started_proxies.retain(|n| *n != error.name);
+                self.proxy_errors.retain(|e| e.name != error.name);
+                self.proxy_errors.push(error.clone());
+                if error.port_conflict {
+                    format!("Frp 代理 {} 端口冲突: {}", error.name, error.error)
+                } else {
+                    format!("Frp 代理 {} 启动失败: {}", error.name, error.error)
+                }
+            }
+            LogEvent::P2pEstablished { name, remote_addr } => {
+                self.set_p2p(P2pStatus {
+                    name: name.clone(),
+                    direct: true,
+                    remote_addr: remote_addr.clone(),
+                    error: None,
+                });
+                match remote_addr {
+                    Some(addr) => format!("Frp {} 已与对端 {} 建立 P2P 直连", name, addr),
+                    None => format!("Frp {} 已与对端建立 P2P 直连", name),
+                }
+            }
+            LogEvent::P2pFailed { name, error } => {
+                self.set_p2p(P2pStatus {
+                    name: name.clone(),
+                    direct: false,
+                    remote_addr: None,
+                    error: Some(error.clone()),
+                });
+                format!("Frp {} P2P 打洞失败: {}", name, error)
+            }
+        }
+    }
+
+    /// 按名称查找 P2P 连接状态
+    pub fn p2p_status(&self, name: &str) -> Option<&P2pStatus> {
+        self.p2p.iter().find(|p| p.name == name)
+    }
+
+    fn set_p2p(&mut self, status: P2pStatus) {
+        self.p2p.retain(|p| p.name != status.name);
+        self.p2p.push(status);
+    }
+}
+
+/// 解析一行 frpc 日志
+///
+/// 支持的日志形如:
+/// - `... login to server success, get run id [abc123]`
+/// - `... connect to server error: token in login doesn't match token from configuration`
+/// - `... login to the server failed: authorization failed`（0.52 之前的版本）
+/// - `... [nchat] start proxy success`
+/// - `... [nchat] start error: port already used`
+/// - `... [<run id>] [ws] establishing nat hole connection successful, sid [...], remoteAddr [1.2.3.4:5678]`
+/// - `... [<run id>] [ws-visitor] make hole error: read udp 0.0.0.0:50632: i/o timeout`
+pub fn parse_log_line(line: &str) -> Option<LogEvent> {
+    let line = strip_ansi(line);
+    let line = line.trim();
+
+    if let Some(pos) = line.find("login to server success") {
+        let run_id = last_bracket(&line[pos..]).map(str::to_string);
+        return Some(LogEvent::LoginSuccess { run_id });
+    }
+    // 开启 loginFailExit 时 frpc 退出前输出的汇总，不含失败原因，原因已由 `connect to server error` 报告
+    if line.contains("With loginFailExit enabled") {
+        return None;
+    }
+    if let Some(pos) = line
+        .find("connect to server error")
+        .or_else(|| line.find("login to the server failed"))
+        .or_else(|| line.find("login to server failed"))
+    {
+        let error = after_colon(&line[pos..]).unwrap_or("未知错误").to_string();
+        let lower = error.to_lowercase();
+        let auth = lower.contains("authorization") || lower.contains("token");
+        return Some(LogEvent::LoginFailed { error, auth });
+    }
+    if let Some(pos) = line.find("start proxy success") {
+        let name = last_bracket(&line[..pos])?.to_string();
+        return Some(LogEvent::ProxyStarted { name });
+    }
+    if let Some(pos) = line.find("start error:") {
+        let name = last_bracket(&line[..pos])?.to_string();
+        let error = line[pos + "start error:".len()..].trim().to_string();
+        let port_conflict = is_port_conflict(&error);
+        return Some(LogEvent::ProxyFailed(ProxyError { name, error, port_conflict }));
+    }
+    if let Some(pos) = line.find("nat hole connection successful") {
+        let name = last_bracket(&line[..pos])?.to_string();
+        let remote_addr = line[pos..]
+            .find("remoteAddr")
+            .and_then(|at| last_bracket(&line[pos + at..]))
+            .map(str::to_string);
+        return Some(LogEvent::P2pEstablished { name, remote_addr });
+    }
+    for keyword in P2P_ERRORS {
+        if let Some(pos) = line.find(keyword) {
+            let name = last_bracket(&line[..pos])?.to_string();
+            let error = after_colon(&line[pos..]).unwrap_or("未知错误").to_string();
+            return Some(LogEvent::P2pFailed { name, error });
+        }
+    }
+    None
+}
+
+/// xtcp 打洞失败时 frpc 输出的日志关键字
+const P2P_ERRORS: &[&str] = &["open tunnel error", "make hole error", "nathole exchange info error", "nathole prepare error"];
+
+fn is_port_conflict(error: &str) -> bool {
+    let lower = error.to_lowercase();
+    ["port already used", "port unavailable", "address already in use", "port not allowed", "already exists"]
+        .iter()
+        .any(|k| lower.contains(k))
+}
+
+/// 取出字符串中最后一个 `[...]` 的内容
+fn last_bracket(s: &str) -> Option<&str> {
+    let end = s.rfind(']')?;
+    let start = s[..end].rfind('[')?;
+    Some(s[start + 1..end].trim()).filter(|name| !name.is_empty())
+}
+
+fn after_colon(s: &str) -> Option<&str> {
+    s.split_once(':').map(|(_, rest)| rest.trim()).filter(|rest| !rest.is_empty())
+}
+
+/// 去掉终端颜色控制序列（frpc 默认输出带颜色的日志）
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// 在后台线程中跟踪 frpc 日志文件，从 `offset` 处开始读取新写入的内容，
+/// 解析出的事件更新到 `connection` 并通过状态通道报告（未设置时直接输出），
+/// `stop` 置位后退出
+pub fn spawn_log_tailer(
+    path: PathBuf,
+    offset: u64,
+    connection: Arc<Mutex<FrpConnection>>,
+    status_sender: Option<Sender<String>>,
+    stop: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader: Option<BufReader<File>> = None;
+        let mut position = offset;
+        let mut partial = String::new();
+
+        while !stop.load(Ordering::SeqCst) {
+            if reader.is_none() {
+                reader = File::open(&path).ok().and_then(|mut file| {
+                    file.seek(SeekFrom::Start(position)).ok()?;
+                    Some(BufReader::new(file))
+                });
+            }
+            let Some(ref mut r) = reader else {
+                thread::sleep(TAIL_INTERVAL);
+                continue;
+            };
+
+            // 日志文件被截断时从头读取
+            if std::fs::metadata(&path).map(|m| m.len() < position).unwrap_or(false) {
+                position = 0;
+                partial.clear();
+                reader = None;
+                continue;
+            }
+
+            let mut buf = String::new();
+            match r.read_line(&mut buf) {
+                Ok(0) | Err(_) => thread::sleep(TAIL_INTERVAL),
+                Ok(n) => {
+                    position += n as u64;
+                    partial.push_str(&buf);
+                    if !partial.ends_with('\n') {
+                        // 行尚未写完，等待剩余部分
+                        continue;
+                    }
+                    if let Some(event) = parse_log_line(&partial) {
+                        let msg = connection.lock().unwrap().apply(&event);
+                        match status_sender {
+                            Some(ref sender) => {
+                                let _ = sender.send(msg);
+                            }
+                            None => println!("{}", msg),
+                        }
+                    }
+                    partial.clear();
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // frpc 0.61 输出的登录和代理日志
+    const LOGIN_SUCCESS: &str = "2024-11-03 10:15:02.101 [I] [client/service.go:295] [a1b2c3d4e5f6a7b8] login to server success, get run id [a1b2c3d4e5f6a7b8]";
+    const AUTH_FAILURE: &str = "2024-11-03 10:15:02.099 [W] [client/service.go:225] connect to server error: token in login doesn't match token from configuration";
+    const CONNECT_REFUSED: &str = "2024-11-03 10:15:02.050 [W] [client/service.go:225] connect to server error: dial tcp 203.0.113.10:7000: connect: connection refused";
+    const LOGIN_FAIL_EXIT: &str = "login to the server failed: first login failed. With loginFailExit enabled, no additional retries will be attempted";
+    const PROXY_SUCCESS: &str = "2024-11-03 10:15:02.345 [I] [client/control.go:170] [a1b2c3d4e5f6a7b8] [nchat] start proxy success";
+    const PROXY_PORT_CONFLICT: &str = "2024-11-03 10:15:02.346 [W] [client/control.go:168] [a1b2c3d4e5f6a7b8] [web] start error: port already used";
+    const PROXY_PORT_NOT_ALLOWED: &str = "2024-11-03 10:15:02.347 [W] [client/control.go:168] [a1b2c3d4e5f6a7b8] [ssh] start error: port not allowed";
+    const PROXY_ERROR: &str = "2024-11-03 10:15:02.348 [W] [client/control.go:168] [a1b2c3d4e5f6a7b8] [api] start error: type [tcpmux] not supported when vhost tcpmux port is not set";
+
+    #[test]
+    fn parses_login_success() {
+        assert_eq!(
+            parse_log_line(LOGIN_SUCCESS),
+            Some(LogEvent::LoginSuccess { run_id: Some("a1b2c3d4e5f6a7b8".to_string()) })
+        );
+    }
+
+    #[test]
+    fn parses_login_failures() {
+        assert_eq!(
+            parse_log_line(AUTH_FAILURE),
+            Some(LogEvent::LoginFailed {
+                error: "token in login doesn't match token from configuration".to_string(),
+                auth: true,
+            })
+        );
+        assert_eq!(
+            parse_log_line(CONNECT_REFUSED),
+            Some(LogEvent::LoginFailed {
+                error: "dial tcp 203.0.113.10:7000: connect: connection refused".to_string(),
+                auth: false,
+            })
+        );
+        // 0.52 之前的版本
+        assert_eq!(
+            parse_log_line("2023-05-01 08:00:00 [W] [service.go:133] login to server failed: authorization failed"),
+            Some(LogEvent::LoginFailed { error: "authorization failed".to_string(), auth: true })
+        );
+    }
+
+    #[test]
+    fn login_fail_exit_summary_keeps_auth_failure() {
+        let mut connection = FrpConnection::default();
+        for line in [AUTH_FAILURE, LOGIN_FAIL_EXIT] {
+            if let Some(event) = parse_log_line(line) {
+                connection.apply(&event);
+            }
+        }
+        assert!(connection.auth_failed);
+        assert!(!connection.logged_in);
+        assert_eq!(
+            connection.login_error.as_deref(),
+            Some("token in login doesn't match token from configuration")
+        );
+    }
+
+    #[test]
+    fn parses_proxy_start_results() {
+        assert_eq!(
+            parse_log_line(PROXY_SUCCESS),
+            Some(LogEvent::ProxyStarted { name: "nchat".to_string() })
+        );
+        assert_eq!(
+            parse_log_line(PROXY_ERROR),
+            Some(LogEvent::ProxyFailed(ProxyError {
+                name: "api".to_string(),
+                error: "type [tcpmux] not supported when vhost tcpmux port is not set".to_string(),
+                port_conflict: false,
+            }))
+        );
+    }
+
+    #[test]
+    fn detects_port_conflicts() {
+        for (line, name, error) in [
+            (PROXY_PORT_CONFLICT, "web", "port already used"),
+            (PROXY_PORT_NOT_ALLOWED, "ssh", "port not allowed"),
+        ] {
+            assert_eq!(
+                parse_log_line(line),
+                Some(LogEvent::ProxyFailed(ProxyError {
+                    name: name.to_string(),
+                    error: error.to_string(),
+                    port_conflict: true,
+                }))
+            );
+        }
+
+        let mut connection = FrpConnection::default();
+        connection.apply(&parse_log_line(PROXY_SUCCESS).unwrap());
+        connection.apply(&parse_log_line(PROXY_PORT_CONFLICT).unwrap());
+        assert_eq!(connection.started_proxies, ["nchat"]);
+        assert_eq!(connection.proxy_errors.len(), 1);
+        assert!(connection.proxy_errors[0].port_conflict);
+    }
+
+    #[test]
+    fn strips_ansi_colors() {
+        let colored = format!("\x1b[1;34m{}\x1b[0m\n", PROXY_SUCCESS);
+        assert_eq!(parse_log_line(&colored), parse_log_line(PROXY_SUCCESS));
+        let colored = format!("\x1b[1;33m{}\x1b[0m", AUTH_FAILURE);
+        assert!(matches!(parse_log_line(&colored), Some(LogEvent::LoginFailed { auth: true, .. })));
+        assert_eq!(strip_ansi("\x1b[0;32mok\x1b[0m"), "ok");
+    }
+
+    #[test]
+    fn ignores_unrelated_lines() {
+        assert_eq!(parse_log_line("2024-11-03 10:15:02.000 [I] [sub/root.go:142] start frpc service for config file [frpc.toml]"), None);
+        assert_eq!(parse_log_line("2024-11-03 10:15:02.001 [I] [client/service.go:212] try to connect to server..."), None);
+        assert_eq!(parse_log_line(""), None);
+    }
+
+    // frpc 0.61 输出的 xtcp 日志，日志前缀为 `[run id] [代理或 visitor 名称]`
+    const VISITOR_HOLE_SUCCESS: &str = "2024-11-03 10:20:11.517 [I] [visitor/xtcp.go:280] [a1b2c3d4e5f6a7b8] [ws-visitor] establishing nat hole connection successful, sid [1730600411234567890], remoteAddr [203.0.113.7:51234]";
+    const PROXY_HOLE_SUCCESS: &str = "2024-11-03 10:20:11.498 [I] [proxy/xtcp.go:112] [0f1e2d3c4b5a6978] [ws] establishing nat hole connection successful, sid [1730600411234567890], remoteAddr [198.51.100.4:61000]";
+    const VISITOR_MAKE_HOLE_ERROR: &str = "2024-11-03 10:21:02.003 [W] [visitor/xtcp.go:273] [a1b2c3d4e5f6a7b8] [ws-visitor] make hole error: read udp 0.0.0.0:50632: i/o timeout";
+    const VISITOR_OPEN_TUNNEL_ERROR: &str = "2024-11-03 10:21:02.004 [E] [visitor/xtcp.go:168] [a1b2c3d4e5f6a7b8] [ws-visitor] open tunnel error: context deadline exceeded";
+    const PROXY_PREPARE_ERROR: &str = "2024-11-03 10:22:40.120 [W] [proxy/xtcp.go:66] [0f1e2d3c4b5a6978] [ws] nathole prepare error: exchange info timeout";
+    const VISITOR_EXCHANGE_ERROR: &str = "2024-11-03 10:22:41.310 [W] [visitor/xtcp.go:251] [a1b2c3d4e5f6a7b8] [ws-visitor] nathole exchange info error: timeout";
+    const VISITOR_FALLBACK: &str = "2024-11-03 10:21:02.004 [I] [visitor/xtcp.go:175] [a1b2c3d4e5f6a7b8] [ws-visitor] try to fallback to visitor [ws-visitor-stcp]";
+
+    #[test]
+    fn parses_p2p_success_with_remote_addr() {
+        assert_eq!(
+            parse_log_line(VISITOR_HOLE_SUCCESS),
+            Some(LogEvent::P2pEstablished {
+                name: "ws-visitor".to_string(),
+                remote_addr: Some("203.0.113.7:51234".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_log_line(PROXY_HOLE_SUCCESS),
+            Some(LogEvent::P2pEstablished {
+                name: "ws".to_string(),
+                remote_addr: Some("198.51.100.4:61000".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_p2p_errors() {
+        let cases = [
+            (VISITOR_MAKE_HOLE_ERROR, "ws-visitor", "read udp 0.0.0.0:50632: i/o timeout"),
+            (VISITOR_OPEN_TUNNEL_ERROR, "ws-visitor", "context deadline exceeded"),
+            (PROXY_PREPARE_ERROR, "ws", "exchange info timeout"),
+            (VISITOR_EXCHANGE_ERROR, "ws-visitor", "timeout"),
+        ];
+        for (line, name, error) in cases {
+            assert_eq!(
+                parse_log_line(line),
+                Some(LogEvent::P2pFailed { name: name.to_string(), error: error.to_string() }),
+                "{}",
+                line
+            );
+        }
+        // 回退本身不是打洞结果
+        assert_eq!(parse_log_line(VISITOR_FALLBACK), None);
+    }
+
+    #[test]
+    fn p2p_status_follows_latest_result() {
+        let mut connection = FrpConnection::default();
+        connection.apply(&parse_log_line(VISITOR_MAKE_HOLE_ERROR).unwrap());
+        let status = connection.p2p_status("ws-visitor").unwrap();
+        assert!(!status.direct);
+        assert_eq!(status.error.as_deref(), Some("read udp 0.0.0.0:50632: i/o timeout"));
+
+        connection.apply(&parse_log_line(VISITOR_HOLE_SUCCESS).unwrap());
+        assert_eq!(connection.p2p.len(), 1);
+        let status = connection.p2p_status("ws-visitor").unwrap();
+        assert!(status.direct);
+        assert_eq!(status.remote_addr.as_deref(), Some("203.0.113.7:51234"));
+        assert_eq!(status.error, None);
+        assert!(connection.p2p_status("ws").is_none());
+    }
+
+    #[test]
+    fn last_bracket_reads_last_pair() {
+        assert_eq!(last_bracket("[a1b2] [ws] "), Some("ws"));
+        assert_eq!(last_bracket("sid [1], remoteAddr [1.2.3.4:5]"), Some("1.2.3.4:5"));
+        assert_eq!(last_bracket("[a1b2] [] "), None);
+        assert_eq!(last_bracket("no brackets"), None);
+        assert_eq!(last_bracket("] ["), None);
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+/// 请求体大小上限
+const MAX_BODY_SIZE: usize = 64 * 1024;
+/// 请求头数量上限
+const MAX_HEADERS: usize = 64;
+/// 请求行和每行请求头的长度上限（含换行）
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// 读取整个请求的时间上限
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 简单的 HTTP/1.1 请求（仅用于本地管理接口）
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// 请求头，名称转为小写
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// 按名称（不区分大小写）取请求头
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+/// HTTP 响应
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// 纯文本响应
+    pub fn text(status: u16, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into().into_bytes(),
+        }
+    }
+
+    /// JSON 响应
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    /// 404 响应
+    pub fn not_found() -> Self {
+        Self::text(404, "text/plain; charset=utf-8", "not found\n")
+    }
+
+    /// 写入响应并关闭连接
+    pub async fn write_to(&self, stream: &mut TcpStream) -> io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason_phrase(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&self.body).await?;
+        stream.shutdown().await
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        415 => "Unsupported Media Type",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// 请求行或请求头超过 `MAX_LINE_LEN`
+#[derive(Debug)]
+struct LineTooLong;
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "请求行或请求头超过 {} 字节", MAX_LINE_LEN)
+    }
+}
+
+impl std::error::Error for LineTooLong {}
+
+/// `read_request` 出错时应答的状态码：超时 408，请求头过长 431，其余 400
+pub fn error_status(err: &io::Error) -> u16 {
+    if err.kind() == io::ErrorKind::TimedOut {
+        408
+    } else if err.get_ref().is_some_and(|e| e.is::<LineTooLong>()) {
+        431
+    } else {
+        400
+    }
+}
+
+/// 从连接中读取一个请求
+///
+/// 请求需在 `REQUEST_TIMEOUT` 内读完，每行长度不超过 `MAX_LINE_LEN`，
+/// 防止客户端在通过认证前占住连接或耗尽内存。
+pub async fn read_request(stream: &mut TcpStream) -> io::Result<HttpRequest> {
+    read_request_within(stream, REQUEST_TIMEOUT).await
+}
+
+async fn read_request_within(stream: &mut TcpStream, timeout: Duration) -> io::Result<HttpRequest> {
+    tokio::time::timeout(timeout, read_request_inner(stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "读取请求超时"))?
+}
+
+/// 读取一行，超过 `MAX_LINE_LEN` 时返回 `LineTooLong` 错误
+async fn read_line_limited<R: AsyncBufRead + Unpin>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let n = reader.take(MAX_LINE_LEN as u64).read_line(line).await?;
+    if n == MAX_LINE_LEN && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, LineTooLong));
+    }
+    Ok(n)
+}
+
+async fn read_request_inner(stream: &mut TcpStream) -> io::Result<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    read_line_limited(&mut reader, &mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(m), Some(t)) => (m.to_string(), t.to_string()),
+        _ => return Err(invalid("无效的请求行")),
+    };
+
+    let mut headers = HashMap::new();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if read_line_limited(&mut reader, &mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(invalid("请求头过多"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim().to_ascii_lowercase();
+            if name == "content-length" {
+                content_length = value.trim().parse::<usize>()
+                    .map_err(|_| invalid("无效的 Content-Length"))?;
+            }
+            headers.insert(name, value.trim().to_string());
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(invalid("请求体过大"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query(query)),
+        None => (target, HashMap::new()),
+    };
+
+    Ok(HttpRequest { method, path, query, headers, body })
+}
+
+/// 解析查询字符串
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match s.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// 比较访问令牌，耗时与内容无关，避免通过响应时间逐字节猜测令牌
+pub fn token_matches(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    expected.len() == given.len()
+        && expected.iter().zip(given).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// 建立一对本地连接，返回（服务端，客户端）
+    async fn connect_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        (server, client)
+    }
+
+    #[tokio::test]
+    async fn reads_request_with_headers_and_body() {
+        let (mut server, mut client) = connect_pair().await;
+        client
+            .write_all(b"POST /send?target=a%20b HTTP/1.1\r\nContent-Length: 5\r\nX-Test: 1\r\n\r\nhello")
+            .await
+            .unwrap();
+
+        let request = read_request(&mut server).await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/send");
+        assert_eq!(request.query["target"], "a b");
+        assert_eq!(request.header("x-test"), Some("1"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_header_line() {
+        let (mut server, mut client) = connect_pair().await;
+        tokio::spawn(async move {
+            let _ = client.write_all(b"GET / HTTP/1.1\r\nX-Long: ").await;
+            // 不断发送，不带换行
+            let chunk = vec![b'a'; 1024];
+            while client.write_all(&chunk).await.is_ok() {}
+        });
+
+        let err = read_request(&mut server).await.unwrap_err();
+        assert_eq!(error_status(&err), 431);
+    }
+
+    #[tokio::test]
+    async fn times_out_stalled_client() {
+        let (mut server, mut client) = connect_pair().await;
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        let err = read_request_within(&mut server, Duration::from_millis(100)).await.unwrap_err();
+        assert_eq!(error_status(&err), 408);
+        drop(client);
+    }
+}
@@ -1,21 +1,63 @@
+#![allow(non_snake_case)]
+
+use std::fmt::Write as _;
+use std::collections::HashMap;
 use std::io::{self, Write, BufWriter};
-use std::net::{SocketAddr, UdpSocket};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::fs::OpenOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use chrono::Local;
-use std::sync::mpsc::{Sender, Receiver, channel};
+use serde::Deserialize;
+use anyhow::Context;
+use std::sync::mpsc::{Receiver, Sender, channel};
 
 // 添加 frp 模块
+pub mod api;
+pub mod bot;
+pub mod config;
 pub mod frp;
-use frp::{FrpManager, FrpConfig, default_frp_config};
+pub mod frp_admin;
+pub mod frp_download;
+pub mod frp_log;
+#[cfg(unix)]
+pub mod daemon;
+mod http;
+pub mod message;
+pub mod metrics;
+pub mod ping;
+pub mod script;
+pub mod stats;
+pub mod webhook;
+pub mod ws;
+use bot::MessageHook;
+use frp_admin::ProxyState;
+use frp_download::DownloadOptions;
+use frp::{ConfigApplied, FrpManager, FrpConfig, FrpMetrics, FrpPaths, ProxyConfig, VisitorConfig, default_frp_config};
+use message::{MessageSubscribers, ReceivedMessage, RecentMessages, DEFAULT_RECENT_CAPACITY};
+use metrics::MetricsSource;
+use stats::{StatsSnapshot, TrafficStats};
 
 const MASTER_VERSION: &str = "1.0.1";
 const BUILD_VERSION: &str = "win0";
 
+/// 消息日志格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[时间] FROM 来源: 内容`
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象
+    Json,
+}
+
+/// 在多个线程之间共享的消息处理器
+pub type SharedHandler = Arc<Mutex<UdpMessageHandler>>;
+
 /// UDP 消息处理器
 pub struct UdpMessageHandler {
     sender_socket: UdpSocket,
@@ -25,6 +67,18 @@ pub struct UdpMessageHandler {
     receive_port: Option<u16>,
     frp_manager: Option<FrpManager>, // 添加 frp 管理器
     status_sender: Option<Sender<String>>, // 新增
+    frp_status_sender: Option<Sender<String>>,
+    stats: Arc<TrafficStats>,
+    frp_metrics: Arc<FrpMetrics>,
+    recent: Arc<RecentMessages>,
+    subscribers: Arc<MessageSubscribers>,
+    hooks: Arc<Mutex<Vec<Box<dyn MessageHook>>>>,
+    bind_address: IpAddr,
+    log_format: LogFormat,
+    nickname: Option<String>,
+    contacts: HashMap<String, String>,
+    frp_defaults: Option<FrpConfig>,
+    frp_paths: FrpPaths,
 }
 
 impl UdpMessageHandler {
@@ -54,9 +108,116 @@ impl UdpMessageHandler {
             receive_port: None,
             frp_manager: None,
             status_sender: None, // 新增
+            frp_status_sender: None,
+            stats: Arc::new(TrafficStats::new()),
+            frp_metrics: Arc::new(FrpMetrics::default()),
+            recent: Arc::new(RecentMessages::new(DEFAULT_RECENT_CAPACITY)),
+            subscribers: Arc::new(MessageSubscribers::default()),
+            hooks: Arc::new(Mutex::new(Vec::new())),
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            log_format: LogFormat::Text,
+            nickname: None,
+            contacts: HashMap::new(),
+            frp_defaults: None,
+            frp_paths: FrpPaths::default(),
         })
     }
     
+    /// 设置接收器绑定的本地地址（默认 0.0.0.0，下次启动接收器时生效）
+    pub fn set_bind_address(&mut self, addr: IpAddr) {
+        self.bind_address = addr;
+    }
+
+    /// 设置消息日志格式（下次启动接收器时生效）
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.log_format = format;
+    }
+
+    /// 设置昵称，设置后发送的消息带有 `昵称: ` 前缀
+    pub fn set_nickname(&mut self, nickname: Option<String>) {
+        self.nickname = nickname;
+    }
+
+    /// 获取昵称
+    pub fn nickname(&self) -> Option<&str> {
+        self.nickname.as_deref()
+    }
+
+    /// 设置联系人（名称到 `IP:端口` 的映射）
+    pub fn set_contacts(&mut self, contacts: HashMap<String, String>) {
+        self.contacts = contacts;
+    }
+
+    /// 获取联系人
+    pub fn contacts(&self) -> &HashMap<String, String> {
+        &self.contacts
+    }
+
+    /// 把联系人名称解析为地址，不是联系人时原样返回
+    pub fn resolve_target<'a>(&'a self, target: &'a str) -> &'a str {
+        self.contacts.get(target).map(|s| s.as_str()).unwrap_or(target)
+    }
+
+    /// 设置 `frp init` 未指定配置且没有保存过 frp 配置时使用的 frp 配置
+    pub fn set_frp_defaults(&mut self, config: Option<FrpConfig>) {
+        self.frp_defaults = config;
+    }
+
+    /// 设置 frp 配置目录、日志文件和 frpc 查找位置（下次 `frp init` 时生效）
+    pub fn set_frp_paths(&mut self, paths: FrpPaths) {
+        self.frp_paths = paths;
+    }
+
+    /// 获取 frp 使用的目录和文件位置
+    pub fn frp_paths(&self) -> &FrpPaths {
+        &self.frp_paths
+    }
+
+    /// 设置 frp 状态通道，frpc 意外退出、自动重启等状态变化会发送到该通道
+    pub fn set_frp_status_sender(&mut self, sender: Sender<String>) {
+        if let Some(ref mut manager) = self.frp_manager {
+            manager.set_status_sender(sender.clone());
+        }
+        self.frp_status_sender = Some(sender);
+    }
+
+    /// 按启动配置自动开启接收器和 frp
+    ///
+    /// 各步骤相互独立：接收器启动失败时仍会尝试启动 frp，frp 的本地端口使用 `port`。
+    /// 返回所有失败步骤的错误信息，由调用方决定如何报告。
+    pub fn autostart(
+        &mut self,
+        port: Option<u16>,
+        start_receiver: bool,
+        start_frp: bool,
+        status_sender: Sender<String>,
+    ) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if start_receiver {
+            match port {
+                Some(port) => {
+                    if let Err(e) = self.start_receiver(port, status_sender) {
+                        errors.push(format!("自动启动接收器失败 (端口 {}): {}", port, e));
+                    }
+                }
+                None => errors.push("自动启动接收器失败: 未配置接收端口 (receive_port)".to_string()),
+            }
+        }
+
+        if start_frp {
+            let mut config = self.base_frp_config();
+            if let Some(port) = port {
+                config.set_nchat_port(port);
+            }
+            if let Err(e) = self.init_frp(Some(config)).and_then(|_| self.start_frp()) {
+                errors.push(format!("自动启动 frp 失败: {:#}", e));
+            }
+        }
+
+        errors
+    }
+
     /// 启动消息接收器
     pub fn start_receiver(&mut self, port: u16, status_sender: Sender<String>) -> io::Result<()> {
         if self.is_receiving() {
@@ -67,7 +228,7 @@ impl UdpMessageHandler {
         }
         
         // 创建接收套接字
-        let receiver_socket = UdpSocket::bind(("0.0.0.0", port))?;
+        let receiver_socket = UdpSocket::bind((self.bind_address, port))?;
         receiver_socket.set_read_timeout(Some(Duration::from_millis(100)))?;
         
         // 设置运行标志
@@ -78,6 +239,11 @@ impl UdpMessageHandler {
         // 克隆共享状态
         let running = self.running.clone();
         let output_file = self.output_file.clone();
+        let stats = self.stats.clone();
+        let recent = self.recent.clone();
+        let subscribers = self.subscribers.clone();
+        let hooks = self.hooks.clone();
+        let log_format = self.log_format;
         
         // 启动接收线程
         let handle = thread::spawn(move || {
@@ -103,23 +269,64 @@ impl UdpMessageHandler {
             while running.load(Ordering::SeqCst) {
                 match receiver_socket.recv_from(&mut buf) {
                     Ok((size, source)) => {
-                        let message = match String::from_utf8(buf[..size].to_vec()) {
-                            Ok(m) => m,
-                            Err(_) => format!("<BINARY DATA: {} bytes>", size)
+                        // 自动应答 ping 回显请求，不写入消息日志，也不计入消息统计
+                        if let Some(reply) = ping::echo_reply(&buf[..size]) {
+                            if let Err(e) = receiver_socket.send_to(&reply, source) {
+                                let _ = status_sender.send(format!("回显应答发送失败: {}", e));
+                            }
+                            continue;
+                        }
+                        stats.record_received(source, size);
+
+                        let (message, binary) = match String::from_utf8(buf[..size].to_vec()) {
+                            Ok(m) => (m, false),
+                            Err(_) => {
+                                stats.record_decode_failure();
+                                (format!("<BINARY DATA: {} bytes>", size), true)
+                            }
                         };
                         
-                        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
-                        let log_entry = format!(
-                            "[{}] FROM {}: {}\n",
-                            timestamp, source, message
-                        );
+                        let received = ReceivedMessage {
+                            timestamp: Local::now(),
+                            source,
+                            text: message,
+                            size,
+                            binary,
+                        };
+
+                        let log_entry = match log_format {
+                            LogFormat::Text => format!(
+                                "[{}] FROM {}: {}\n",
+                                received.timestamp.format("%Y-%m-%d %H:%M:%S%.3f"), source, received.text
+                            ),
+                            LogFormat::Json => format!("{}\n", received.to_json()),
+                        };
                         
                         // 只写入文件，不输出到控制台
                         if let Some(writer) = &mut file_writer {
                             if let Err(e) = writer.write_all(log_entry.as_bytes()) {
+                                stats.record_write_error();
                                 let _ = status_sender.send(format!("文件写入错误: {}", e));
                             }
                         }
+                        let dropped = subscribers.publish(&received);
+                        if dropped > 0 {
+                            stats.record_subscriber_drops(dropped);
+                        }
+
+                        // 执行消息钩子，把回复发回消息来源
+                        for hook in hooks.lock().unwrap().iter_mut() {
+                            if let Some(reply) = hook.on_message(&received) {
+                                match receiver_socket.send_to(reply.as_bytes(), source) {
+                                    Ok(sent) => stats.record_sent(source, sent),
+                                    Err(e) => {
+                                        let _ = status_sender.send(format!("钩子回复发送失败: {}", e));
+                                    }
+                                }
+                            }
+                        }
+
+                        recent.push(received);
                     }
                     Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
                     e.kind() == io::ErrorKind::TimedOut => {
@@ -137,6 +344,7 @@ impl UdpMessageHandler {
                         
                     
                     Err(e) => {
+                        stats.record_receive_error();
                         let _ = status_sender.send(format!("接收错误: {}", e));
                     }
                 }
@@ -171,7 +379,7 @@ impl UdpMessageHandler {
         self.receiver_thread.is_some()
     }
     
-    /// 发送消息到指定地址
+    // 发送消息到指定地址
     // pub fn send_message(&self, target: &str, message: &str) -> io::Result<usize> {
     //     let addr: SocketAddr = target.parse().map_err(|e| {
     //         io::Error::new(
@@ -182,8 +390,13 @@ impl UdpMessageHandler {
         
     //     self.sender_socket.send_to(message.as_bytes(), addr)
     // }
+
+    /// 发送消息到指定地址
+    ///
+    /// `target` 可以是 `IP:端口` 或联系人名称；设置了昵称时消息带有 `昵称: ` 前缀。
     pub fn send_message(&self, target: &str, message: &str) -> io::Result<usize> {
         // 解析目标地址
+        let target = self.resolve_target(target);
         let addr: SocketAddr = match target.parse() {
             Ok(addr) => addr,
             Err(e) => {
@@ -198,8 +411,15 @@ impl UdpMessageHandler {
         self.sender_socket.set_write_timeout(Some(Duration::from_secs(3)))?;
         
         // 发送消息
-        match self.sender_socket.send_to(message.as_bytes(), &addr) {
-            Ok(size) => Ok(size),
+        let payload = match self.nickname {
+            Some(ref nickname) => format!("{}: {}", nickname, message),
+            None => message.to_string(),
+        };
+        match self.sender_socket.send_to(payload.as_bytes(), addr) {
+            Ok(size) => {
+                self.stats.record_sent(addr, size);
+                Ok(size)
+            }
             Err(e) => {
                 // 处理 Windows 特有的错误报告问题
                 if cfg!(windows) && e.kind() == io::ErrorKind::TimedOut {
@@ -228,24 +448,231 @@ impl UdpMessageHandler {
     pub fn output_file(&self) -> &PathBuf {
         &self.output_file
     }
+
+    /// 获取自程序启动以来的流量统计
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// 获取共享的流量统计，供后台服务记录计数
+    pub fn traffic_stats(&self) -> Arc<TrafficStats> {
+        self.stats.clone()
+    }
+
+    /// 生成状态报告（用于 status 命令和控制接口）
+    pub fn status_report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "=== NChat 状态 ===");
+        match self.local_send_port() {
+            Ok(port) => { let _ = writeln!(out, "发送端口: {}", port); }
+            Err(e) => { let _ = writeln!(out, "获取发送端口失败: {}", e); }
+        }
+
+        let _ = writeln!(out, "接收器状态: {}",
+            if self.is_receiving() { "运行中" } else { "已停止" });
+
+        if let Some(port) = self.receive_port() {
+            let _ = writeln!(out, "接收端口: {}", port);
+        }
+
+        let _ = writeln!(out, "消息保存路径: {}", self.output_file().display());
+        if let Some(ref nickname) = self.nickname {
+            let _ = writeln!(out, "昵称: {}", nickname);
+        }
+
+        let stats = self.stats();
+        let _ = writeln!(out, "已发送: {} 个报文 / {} 字节", stats.packets_sent, stats.bytes_sent);
+        let _ = writeln!(out, "已接收: {} 个报文 / {} 字节", stats.packets_received, stats.bytes_received);
+        let _ = writeln!(out, "错误: 解码失败 {} / 写入错误 {} / 接收错误 {} / 推送丢弃 {}",
+            stats.decode_failures, stats.write_errors, stats.receive_errors, stats.subscriber_drops);
+
+        // 显示 frp 状态
+        let _ = writeln!(out, "\n=== Frp 内网穿透状态 ===");
+
+        if let Some(status) = self.get_frp_status() {
+            let _ = writeln!(out, "运行状态: {}", status.state.label());
+            let _ = writeln!(out, "服务器地址: {}:{}", status.config.server_addr, status.config.server_port);
+            write_proxy_list(&mut out, &status.config.proxies);
+        } else {
+            let _ = writeln!(out, "运行状态: 已停止");
+            let _ = writeln!(out, "Frp 未初始化");
+        }
+        out
+    }
+
+    /// 获取最近接收的消息（最多 `limit` 条，按时间顺序）
+    pub fn recent_messages(&self, limit: usize) -> Vec<ReceivedMessage> {
+        self.recent.latest(limit)
+    }
+
+    /// 订阅之后接收到的消息
+    ///
+    /// 队列最多缓存 `capacity` 条消息，队列满时新消息会被丢弃，不会阻塞接收线程。
+    pub fn subscribe(&self, capacity: usize) -> Receiver<ReceivedMessage> {
+        self.subscribers.subscribe(capacity)
+    }
+
+    /// 注册消息钩子，钩子的回复会自动发回消息来源地址
+    ///
+    /// 钩子对之后接收到的所有消息生效（包括已在运行的接收器）。
+    pub fn add_hook<H: MessageHook + 'static>(&self, hook: H) {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// 获取指标导出所需的共享数据
+    pub fn metrics_source(&self) -> MetricsSource {
+        MetricsSource {
+            stats: self.stats.clone(),
+            receiver_running: self.running.clone(),
+            frp: self.frp_metrics.clone(),
+        }
+    }
     
     // ========== Frp 相关方法 ==========
+
+    /// 创建共享进程指标的 frp 管理器
+    fn new_frp_manager(&self, config: FrpConfig) -> anyhow::Result<FrpManager> {
+        let mut frp_manager = FrpManager::new(config, self.frp_paths.clone())?;
+        frp_manager.set_metrics(self.frp_metrics.clone());
+        if let Some(ref sender) = self.frp_status_sender {
+            frp_manager.set_status_sender(sender.clone());
+        }
+        Ok(frp_manager)
+    }
     
+    /// 未指定 frp 配置时使用的配置
+    ///
+    /// 依次使用上次 `frp config` 保存的配置、配置文件中的 `[frp]` 和内置默认配置。
+    fn base_frp_config(&self) -> FrpConfig {
+        match FrpConfig::load_saved(&self.frp_paths.config_dir) {
+            Ok(Some(config)) => return config,
+            Ok(None) => {}
+            Err(e) => eprintln!("读取保存的 frp 配置失败: {:#}", e),
+        }
+        self.frp_defaults.clone().unwrap_or_else(default_frp_config)
+    }
+
+    /// 当前 frp 配置：已初始化时为管理器中的配置，否则为 `base_frp_config`
+    fn current_frp_config(&self) -> FrpConfig {
+        match self.frp_manager {
+            Some(ref manager) => manager.config().clone(),
+            None => self.base_frp_config(),
+        }
+    }
+
+    /// 修改当前 frp 配置并保存
+    ///
+    /// 已初始化时同时更新管理器中的配置，正在运行的 frpc 尽量热重载（见 `apply_frp_config`）。
+    fn modify_frp_config<F>(&mut self, modify: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut FrpConfig) -> anyhow::Result<()>,
+    {
+        let mut config = self.current_frp_config();
+        if let Some(ref path) = config.config_file {
+            return Err(anyhow::anyhow!(
+                "正在原样使用配置文件 {}，请直接编辑该文件或使用 frp config 重新配置",
+                path.display()
+            ));
+        }
+        modify(&mut config)?;
+        config.validate()?;
+        config.save(&self.frp_paths.config_dir)?;
+        if let Some(ref mut manager) = self.frp_manager {
+            apply_frp_config(manager, config);
+        }
+        Ok(())
+    }
+
+    /// 当前 frp 配置中的代理列表
+    pub fn frp_proxies(&self) -> Vec<ProxyConfig> {
+        self.current_frp_config().proxies
+    }
+
+    /// 添加 frp 代理
+    pub fn add_frp_proxy(&mut self, proxy: ProxyConfig) -> anyhow::Result<()> {
+        self.modify_frp_config(|config| config.add_proxy(proxy))
+    }
+
+    /// 设置 frp 客户端选项（可用选项见 `frp::FRP_OPTIONS`），值为 `default` 时恢复默认值
+    pub fn set_frp_option(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        self.modify_frp_config(|config| config.set_option(key, value))
+    }
+
+    /// 删除 frp 代理
+    pub fn remove_frp_proxy(&mut self, name: &str) -> anyhow::Result<()> {
+        self.modify_frp_config(|config| config.remove_proxy(name).map(|_| ()))
+    }
+
+    /// 当前 frp 配置中的 visitor 列表
+    pub fn frp_visitors(&self) -> Vec<VisitorConfig> {
+        self.current_frp_config().visitors
+    }
+
+    /// 添加 frp visitor
+    pub fn add_frp_visitor(&mut self, visitor: VisitorConfig) -> anyhow::Result<()> {
+        self.modify_frp_config(|config| config.add_visitor(visitor))
+    }
+
+    /// 删除 frp visitor
+    pub fn remove_frp_visitor(&mut self, name: &str) -> anyhow::Result<()> {
+        self.modify_frp_config(|config| config.remove_visitor(name).map(|_| ()))
+    }
+
+    /// 生成对端访问本机 stcp/sudp/xtcp 代理所需的命令说明
+    ///
+    /// 输出中包含代理的 secret key，只应发给信任的对端。
+    pub fn frp_share_report(&self, proxy_name: &str, bind_port: Option<u16>) -> anyhow::Result<String> {
+        let config = self.current_frp_config();
+        let command = config.visitor_command(proxy_name, bind_port)?;
+        let proxy = config.proxy(proxy_name).cloned().unwrap_or_default();
+        let bind_port = bind_port.unwrap_or_else(|| frp::default_visitor_port(proxy.local_port));
+
+        let mut out = String::new();
+        let _ = writeln!(out, "在对端 NChat 中执行以下命令 (包含 secret key，请通过安全渠道发送):");
+        let token = if config.has_token() { " <token>" } else { "" };
+        let _ = writeln!(out, "  frp config {} {}{}", config.server_addr, config.server_port, token);
+        let _ = writeln!(out, "  {}", command);
+        let _ = writeln!(out, "  frp start");
+        let route = match (proxy.protocol.as_str(), proxy.stcp_fallback) {
+            ("xtcp", true) => "优先与本机 P2P 直连（打洞失败时经 frp 服务器中转）",
+            ("xtcp", false) => "与本机 P2P 直连（打洞失败时无法连接）",
+            _ => "经 frp 服务器转发",
+        };
+        let _ = writeln!(
+            out,
+            "之后对端发往 {}:{} 的流量会{}到本机的代理 {}",
+            frp::DEFAULT_VISITOR_BIND_ADDR,
+            bind_port,
+            route,
+            proxy_name
+        );
+        if proxy.protocol == "xtcp" {
+            let _ = writeln!(out, "提示: xtcp 只转发 TCP 流量，只适用于 WebSocket、HTTP API 等 TCP 服务，不能用于收发聊天消息");
+        }
+        if let Some(ref user) = config.user {
+            if proxy.allow_users.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "提示: 本机的 user 为 {}，frp 默认只允许相同 user 的 visitor 访问；对端使用其他 user 时，请重新添加代理并加上 --allow-user <对端 user>",
+                    user
+                );
+            }
+        }
+        Ok(out)
+    }
+
     /// 初始化 frp 管理器
+    ///
+    /// 未指定配置时使用 `base_frp_config`。
     pub fn init_frp(&mut self, config: Option<FrpConfig>) -> anyhow::Result<()> {
-        let config = config.unwrap_or_else(default_frp_config);
-        let mut frp_manager = FrpManager::new(config)?;
+        let mut config = config.unwrap_or_else(|| self.base_frp_config());
         
-        // 设置本地端口为当前接收端口
+        // NChat 自身代理的本地端口设为当前接收端口
         if let Some(port) = self.receive_port {
-            // 这里需要修改 frp 配置的本地端口
-            // 由于 FrpConfig 是值类型，我们需要重新创建
-            let mut new_config = frp_manager.get_status().config;
-            new_config.local_port = port;
-            frp_manager = FrpManager::new(new_config)?;
+            config.set_nchat_port(port);
         }
         
-        self.frp_manager = Some(frp_manager);
+        self.frp_manager = Some(self.new_frp_manager(config)?);
         println!("Frp 管理器已初始化");
         Ok(())
     }
@@ -282,30 +709,157 @@ impl UdpMessageHandler {
         self.frp_manager.as_ref().map(|m| m.get_status())
     }
     
-    /// 下载 frp 客户端
-    pub async fn download_frp(&mut self) -> anyhow::Result<()> {
-        if let Some(ref mut frp_manager) = self.frp_manager {
-            frp_manager.download_frp_if_needed().await?;
-            Ok(())
+    /// 生成 frp 详细状态报告
+    ///
+    /// frpc 运行时会通过管理接口查询代理的实时状态。查询可能等待数秒，
+    /// 因此只在锁内复制状态和管理接口句柄，释放锁后再查询。
+    pub fn frp_status_report(shared: &SharedHandler) -> String {
+        let (status, admin) = {
+            let handler = shared.lock().unwrap();
+            match handler.frp_manager.as_ref() {
+                Some(frp_manager) => (Some(frp_manager.get_status()), frp_manager.live_admin()),
+                None => (None, None),
+            }
+        };
+        let live_proxies = admin.map(|admin| admin.proxy_status());
+
+        let mut out = String::new();
+        let _ = writeln!(out, "=== Frp 状态 ===");
+
+        if let Some(status) = status {
+            let _ = writeln!(out, "运行状态: {}", status.state.label());
+            let _ = writeln!(out, "重启次数: {}", status.restarts);
+            if let Some(ref exit) = status.last_exit {
+                let _ = writeln!(out, "上次意外退出: {}", exit);
+            }
+            if status.restart_pending {
+                let _ = writeln!(out, "提示: 部分设置尚未生效，重启 frp 后才会使用下方的配置");
+            }
+            let live_states = live_proxies.as_ref().and_then(|r| r.as_ref().ok());
+            write_frp_connection(&mut out, &status.connection, live_states.is_none());
+            match live_proxies {
+                Some(Ok(ref states)) => write_live_proxies(&mut out, states),
+                Some(Err(e)) => {
+                    let _ = writeln!(out, "代理实时状态: 无法查询 ({:#})", e);
+                }
+                None => {}
+            }
+            let _ = writeln!(out, "服务器地址: {}:{}", status.config.server_addr, status.config.server_port);
+            write_frp_options(&mut out, &status.config);
+            write_proxy_list(&mut out, &status.config.proxies);
+            if !status.config.visitors.is_empty() {
+                write_visitor_list(&mut out, &status.config.visitors);
+            }
+            write_p2p_paths(&mut out, &status.config, &status.connection);
+            match (&status.config.token_env, &status.config.token) {
+                (Some(var), _) => {
+                    let _ = writeln!(out, "认证令牌: 来自环境变量 {}", var);
+                }
+                (None, Some(_)) => {
+                    let _ = writeln!(out, "认证令牌: {}", frp::TOKEN_MASK);
+                }
+                (None, None) => {}
+            }
+            let _ = writeln!(out, "配置文件: {}", status.config_path.display());
+            let _ = writeln!(out, "日志文件: {}", status.log_file.display());
+            if status.config.config_file.is_some() {
+                let _ = writeln!(out, "配置模式: 原样使用用户提供的配置文件");
+            }
         } else {
-            Err(anyhow::anyhow!("请先初始化 frp 管理器"))
+            let _ = writeln!(out, "运行状态: 已停止");
+            let _ = writeln!(out, "Frp 未初始化");
+        }
+        out
+    }
+    
+    /// 让已初始化的 frp 管理器使用刚下载的 frpc
+    pub fn use_downloaded_frpc(&mut self, frpc_path: PathBuf) {
+        if let Some(ref mut frp_manager) = self.frp_manager {
+            frp_manager.set_frp_path(frpc_path);
+        }
+    }
+
+    /// 查找启动 frp 时要运行的 frpc
+    pub fn locate_frpc(&self) -> anyhow::Result<PathBuf> {
+        match self.frp_manager {
+            Some(ref frp_manager) => frp_manager.locate_frpc(),
+            None => self.frp_paths.locate_frpc(self.base_frp_config().version.as_deref()),
         }
     }
+
+    /// 生成 frpc 版本信息报告：当前使用的 frpc 及其版本、托管目录中已安装的版本
+    pub fn frp_version_report(&self) -> String {
+        frp_download::version_report(self.locate_frpc(), &self.frp_paths.tools_dir)
+    }
     
+    /// 从已有的 frpc TOML 配置文件加载 frp 配置
+    ///
+    /// `verbatim` 为真时启动 frpc 直接使用该文件，不再生成 frpc.toml；
+    /// 否则把文件内容解析为 NChat 的配置，之后按该配置生成 frpc.toml。
+    /// 加载的配置会被保存，下次运行时 `frp init` 会重新读取。
+    pub fn load_frp_config(&mut self, path: &Path, verbatim: bool) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("读取 {} 失败", path.display()))?;
+        let mut config = FrpConfig::from_frpc_toml(&content)?;
+        if verbatim {
+            let path = std::fs::canonicalize(path)
+                .with_context(|| format!("无法解析路径 {}", path.display()))?;
+            config.config_file = Some(path);
+            // frpc 直接读取该文件中的 token，无需另外保存
+            config.token = None;
+            config.token_env = None;
+        }
+
+        config.save(&self.frp_paths.config_dir)?;
+        self.frp_manager = Some(self.new_frp_manager(config)?);
+        println!("已加载 frp 配置: {}{}", path.display(), if verbatim { " (原样使用)" } else { "" });
+        Ok(())
+    }
+
     /// 配置 frp 服务器
-    pub fn configure_frp(&mut self, server_addr: &str, server_port: u16, token: Option<&str>) -> anyhow::Result<()> {
-        let config = FrpConfig {
+    ///
+    /// `protocol` 为 NChat 自身代理的类型，`None` 时保持原有类型（默认为 UDP）。
+    /// 新配置会保存到 frp 配置目录，下次运行时 `frp init` 会重新读取。
+    pub fn configure_frp(
+        &mut self,
+        server_addr: &str,
+        server_port: u16,
+        token: Option<&str>,
+        protocol: Option<&str>,
+    ) -> anyhow::Result<()> {
+        // `env:变量名` 表示从环境变量读取 token
+        let (token, token_env) = match token {
+            Some(token) => match token.strip_prefix(TOKEN_ENV_PREFIX) {
+                Some(var) if !var.is_empty() => (None, Some(var.to_string())),
+                Some(_) => return Err(anyhow::anyhow!("{} 后需要指定环境变量名", TOKEN_ENV_PREFIX)),
+                None => (Some(token.to_string()), None),
+            },
+            None => (None, None),
+        };
+        let mut config = FrpConfig {
             server_addr: server_addr.to_string(),
             server_port,
-            token: token.map(|t| t.to_string()),
-            local_port: self.receive_port.unwrap_or(8080),
-            remote_port: None,
-            protocol: "tcp".to_string(),
-            name: "nchat".to_string(),
+            token,
+            token_env,
+            config_file: None,
+            ..self.current_frp_config()
         };
+        if let Some(protocol) = protocol {
+            let local_port = self.receive_port.unwrap_or(default_frp_config().proxies[0].local_port);
+            config.set_nchat_protocol(protocol, local_port)?;
+        }
+        if let Some(port) = self.receive_port {
+            config.set_nchat_port(port);
+        }
+        config.validate()?;
         
-        self.frp_manager = Some(FrpManager::new(config)?);
+        config.save(&self.frp_paths.config_dir)?;
         println!("Frp 配置已更新");
+        match self.frp_manager {
+            // 保留已有的管理器，正在运行的 frpc 不会被结束
+            Some(ref mut manager) => apply_frp_config(manager, config),
+            None => self.frp_manager = Some(self.new_frp_manager(config)?),
+        }
         Ok(())
     }
 }
@@ -316,25 +870,260 @@ impl Drop for UdpMessageHandler {
     }
 }
 
+/// 把新配置交给管理器，并说明正在运行的 frpc 是否已使用新配置
+fn apply_frp_config(manager: &mut FrpManager, config: FrpConfig) {
+    match manager.apply_config(config) {
+        Ok(ConfigApplied::Saved) => {}
+        Ok(ConfigApplied::Reloaded) => println!("frpc 已热重载新的代理和 visitor 配置"),
+        Ok(ConfigApplied::RestartRequired) => {
+            println!("提示: 服务器或客户端设置有变化，frpc 不支持热重载这些设置，重启 frp (frp stop 后 frp start) 后才会生效")
+        }
+        Err(e) => eprintln!("热重载失败: {:#}，重启 frp 后新配置才会生效", e),
+    }
+}
+
+/// `frp config` 命令用法
+pub(crate) const FRP_CONFIG_USAGE: &str = "frp config <服务器地址> <端口> [token|env:变量名] [--type udp|sudp]";
+/// `frp config` 的 token 参数以此开头时表示从环境变量读取 token
+const TOKEN_ENV_PREFIX: &str = "env:";
+
+/// 从 `frp config` 参数中取出 `--type <类型>`，返回其余参数和类型
+pub(crate) fn split_type_option<'a>(args: &[&'a str]) -> Result<(Vec<&'a str>, Option<&'a str>), String> {
+    let mut rest = Vec::new();
+    let mut protocol = None;
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        if arg == "--type" {
+            let value = iter.next().ok_or("--type 需要指定代理类型")?;
+            protocol = Some(*value);
+        } else {
+            rest.push(arg);
+        }
+    }
+    Ok((rest, protocol))
+}
+
+/// `frp visitor share` 命令用法
+pub(crate) const FRP_SHARE_USAGE: &str = "frp visitor share <代理名> [对端本地端口]";
+
+/// `frp set` 命令用法
+pub(crate) const FRP_SET_USAGE: &str = "frp set <选项> <值|default>";
+
+/// `frp download` 命令用法
+pub(crate) const FRP_DOWNLOAD_USAGE: &str = "frp download [--version 版本] [镜像地址前缀]";
+
+/// 解析 `frp download` 参数
+pub(crate) fn parse_download_args(args: &[&str], mut options: DownloadOptions) -> Result<DownloadOptions, String> {
+    let mut iter = args.iter();
+    while let Some(&arg) = iter.next() {
+        if arg == "--version" {
+            let value = iter.next().ok_or("--version 需要指定版本号")?;
+            options.version = frp_download::parse_version(value).map_err(|e| e.to_string())?;
+        } else if arg.starts_with("--") {
+            return Err(format!("未知的选项: {}", arg));
+        } else {
+            options.mirror = arg.to_string();
+        }
+    }
+    Ok(options)
+}
+
+/// 输出从 frpc 日志解析出的连接状态
+fn write_frp_connection(out: &mut String, connection: &frp_log::FrpConnection, show_proxies: bool) {
+    let login = if connection.logged_in {
+        match connection.run_id {
+            Some(ref run_id) => format!("已登录 (run id {})", run_id),
+            None => "已登录".to_string(),
+        }
+    } else if let Some(ref error) = connection.login_error {
+        let kind = if connection.auth_failed { "认证失败" } else { "登录失败" };
+        format!("{}: {}", kind, error)
+    } else {
+        "未连接".to_string()
+    };
+    let _ = writeln!(out, "服务器连接: {}", login);
+    if !show_proxies {
+        return;
+    }
+    for name in &connection.started_proxies {
+        let _ = writeln!(out, "  代理 {}: 已启动", name);
+    }
+    for error in &connection.proxy_errors {
+        let kind = if error.port_conflict { "端口冲突" } else { "启动失败" };
+        let _ = writeln!(out, "  代理 {}: {} ({})", error.name, kind, error.error);
+    }
+}
+
+/// 输出 frpc 管理接口报告的代理实时状态
+fn write_live_proxies(out: &mut String, states: &[ProxyState]) {
+    if states.is_empty() {
+        let _ = writeln!(out, "代理实时状态: 无");
+        return;
+    }
+    let _ = writeln!(out, "代理实时状态:");
+    for state in states {
+        let mut line = format!("  {} [{}] {}", state.name, state.proxy_type, state.label());
+        if !state.remote_addr.is_empty() {
+            line.push_str(&format!(" -> 远程地址 {}", state.remote_addr));
+        }
+        if !state.err.is_empty() {
+            line.push_str(&format!(" ({})", state.err));
+        }
+        let _ = writeln!(out, "{}", line);
+    }
+}
+
+/// 输出 frp 代理列表
+pub(crate) fn write_proxy_list(out: &mut String, proxies: &[ProxyConfig]) {
+    if proxies.is_empty() {
+        let _ = writeln!(out, "代理: 无");
+        return;
+    }
+    let _ = writeln!(out, "代理:");
+    for proxy in proxies {
+        let local = match proxy.local_ip {
+            Some(ref ip) => format!("本地地址 {}:{}", ip, proxy.local_port),
+            None => format!("本地端口 {}", proxy.local_port),
+        };
+        let remote = if proxy.is_secret() {
+            " -> 仅 visitor 访问".to_string()
+        } else {
+            proxy.remote_port.map(|p| format!(" -> 远程端口 {}", p)).unwrap_or_default()
+        };
+        let mut flags = Vec::new();
+        if proxy.use_encryption {
+            flags.push("加密");
+        }
+        if proxy.use_compression {
+            flags.push("压缩");
+        }
+        if proxy.stcp_fallback {
+            flags.push("stcp 回退");
+        }
+        let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join("，")) };
+        let _ = writeln!(out, "  {} [{}] {}{}{}", proxy.name, proxy.protocol, local, remote, flags);
+    }
+}
+
+/// 输出 frp visitor 列表（不含 secret key）
+pub(crate) fn write_visitor_list(out: &mut String, visitors: &[VisitorConfig]) {
+    if visitors.is_empty() {
+        let _ = writeln!(out, "visitor: 无");
+        return;
+    }
+    let _ = writeln!(out, "visitor:");
+    for visitor in visitors {
+        let server = match visitor.server_user {
+            Some(ref user) => format!("{}.{}", user, visitor.server_name),
+            None => visitor.server_name.clone(),
+        };
+        let mut flags = Vec::new();
+        if visitor.stcp_fallback {
+            match visitor.fallback_timeout_ms {
+                Some(timeout) => flags.push(format!("stcp 回退，超时 {} 毫秒", timeout)),
+                None => flags.push("stcp 回退".to_string()),
+            }
+        }
+        if visitor.keep_tunnel_open {
+            flags.push("保持隧道".to_string());
+        }
+        let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join("，")) };
+        let _ = writeln!(
+            out,
+            "  {} [{}] {}:{} -> 对端代理 {}{}",
+            visitor.name,
+            visitor.protocol,
+            visitor.bind_addr(),
+            visitor.bind_port,
+            server,
+            flags
+        );
+    }
+}
+
+/// 输出 xtcp 代理和 visitor 当前使用的连接路径：P2P 直连或经服务器的 stcp 回退
+///
+/// xtcp 只转发 TCP 流量，P2P 只用于 WebSocket、HTTP API 等 TCP 服务，聊天消息仍经 udp/sudp 代理收发。
+fn write_p2p_paths(out: &mut String, config: &FrpConfig, connection: &frp_log::FrpConnection) {
+    let entries: Vec<(&str, bool)> = config
+        .proxies
+        .iter()
+        .filter(|p| p.protocol == "xtcp")
+        .map(|p| (p.name.as_str(), p.stcp_fallback))
+        .chain(
+            config
+                .visitors
+                .iter()
+                .filter(|v| v.protocol == "xtcp")
+                .map(|v| (v.name.as_str(), v.stcp_fallback)),
+        )
+        .collect();
+    if entries.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "P2P 连接路径 (只用于 WebSocket、HTTP API 等 TCP 服务，聊天消息不经过 P2P):");
+    for (name, fallback) in entries {
+        let path = match connection.p2p_status(name) {
+            Some(status) if status.direct => match status.remote_addr {
+                Some(ref addr) => format!("P2P 直连 (对端 {})", addr),
+                None => "P2P 直连".to_string(),
+            },
+            Some(status) => {
+                let error = status.error.as_deref().unwrap_or("未知错误");
+                if fallback {
+                    format!("经服务器中转 (stcp 回退，打洞失败: {})", error)
+                } else {
+                    format!("不可用 (打洞失败且未开启 stcp 回退: {})", error)
+                }
+            }
+            None => "尚未建立 (有连接时才会打洞)".to_string(),
+        };
+        let _ = writeln!(out, "  {}: {}", name, path);
+    }
+}
+
+/// 输出已设置的 frp 客户端选项
+fn write_frp_options(out: &mut String, config: &FrpConfig) {
+    if let Some(ref user) = config.user {
+        let _ = writeln!(out, "用户: {}", user);
+    }
+    if let Some(login_fail_exit) = config.login_fail_exit {
+        let _ = writeln!(out, "登录失败时退出: {}", if login_fail_exit { "是" } else { "否" });
+    }
+    if let Some(ref level) = config.log_level {
+        let _ = writeln!(out, "日志级别: {}", level);
+    }
+    let transport = config.transport.describe();
+    if !transport.is_empty() {
+        let _ = writeln!(out, "传输设置: {}", transport.join("，"));
+    }
+}
+
 /// 用户输入处理器
 pub struct InputHandler;
 
 impl InputHandler {
     /// 处理用户命令
+    ///
+    /// 只在调用处理器时加锁：等待用户输入、ping 等耗时操作期间，HTTP API、WebSocket
+    /// 等其他使用处理器的服务不会被阻塞。
     pub fn handle_command(
         &self, 
         command: &str, 
-        handler: &mut UdpMessageHandler,
+        handler: &SharedHandler,
     ) -> bool {
         let parts: Vec<&str> = command.split_whitespace().collect();
-        let cmd = parts.get(0).unwrap_or(&"");
+        let cmd = parts.first().unwrap_or(&"");
         
         match *cmd {
             "send" => self.handle_send(handler),
             "start" => self.handle_start(handler),
-            "stop" => self.handle_stop(handler),
-            "status" => self.handle_status(handler),
+            "stop" => self.handle_stop(&mut handler.lock().unwrap()),
+            "status" => self.handle_status(&handler.lock().unwrap()),
+            "stats" => self.handle_stats(&handler.lock().unwrap()),
             "version" => self.handle_version(),
+            "ping" => self.handle_ping(handler, &parts[1..]),
+            "contacts" => self.handle_contacts(&handler.lock().unwrap()),
             "frp" => {
                 self.handle_frp(handler, &parts[1..]);
             }
@@ -350,9 +1139,9 @@ impl InputHandler {
     }
 
     /// 处理发送命令
-    fn handle_send(&self, handler: &UdpMessageHandler) {
+    fn handle_send(&self, handler: &SharedHandler) {
         // 获取目标地址
-        let target = match self.prompt_input("请输入目标地址 (格式: IP:端口, 例如 127.0.0.1:8080)") {
+        let target = match self.prompt_input("请输入目标地址 (格式: IP:端口 或联系人名称, 例如 127.0.0.1:8080)") {
             Some(addr) => addr,
             None => return,
         };
@@ -364,15 +1153,16 @@ impl InputHandler {
         };
 
         // 发送消息
-        match handler.send_message(&target, &message) {
+        let result = handler.lock().unwrap().send_message(&target, &message);
+        match result {
             Ok(size) => println!("成功发送 {} 字节到 {}", size, target),
             Err(e) => eprintln!("发送失败: {}", e),
         }
     }
     
     /// 处理启动接收命令
-    fn handle_start(&self, handler: &mut UdpMessageHandler) {
-        if handler.is_receiving() {
+    fn handle_start(&self, handler: &SharedHandler) {
+        if handler.lock().unwrap().is_receiving() {
             println!("接收器已在运行");
             return;
         }
@@ -385,7 +1175,8 @@ impl InputHandler {
         match port.parse::<u16>() {
             Ok(port_num) => {
                 let (status_tx, status_rx) = channel::<String>();
-                if let Err(e) = handler.start_receiver(port_num, status_tx) {
+                let result = handler.lock().unwrap().start_receiver(port_num, status_tx);
+                if let Err(e) = result {
                     eprintln!("启动接收器失败: {}", e);
                 } else {
                     // 启动一个线程来监听状态通道
@@ -412,32 +1203,150 @@ impl InputHandler {
     
     /// 处理状态命令
     fn handle_status(&self, handler: &UdpMessageHandler) {
-        println!("=== NChat 状态 ===");
-        match handler.local_send_port() {
-            Ok(port) => println!("发送端口: {}", port),
-            Err(e) => eprintln!("获取发送端口失败: {}", e),
+        print!("{}", handler.status_report());
+    }
+
+    /// 处理延迟测量命令
+    fn handle_ping(&self, handler: &SharedHandler, args: &[&str]) {
+        let target = match args.first() {
+            Some(target) => handler.lock().unwrap().resolve_target(target).to_string(),
+            None => {
+                println!("用法: ping <IP:端口> [次数]");
+                return;
+            }
+        };
+        let count = match args.get(1) {
+            Some(c) => match c.parse::<u32>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    eprintln!("无效的次数: {}", c);
+                    return;
+                }
+            },
+            None => 4,
+        };
+
+        if let Err(e) = ping::ping(&target, count, ping::DEFAULT_PING_INTERVAL, ping::DEFAULT_PING_TIMEOUT) {
+            eprintln!("ping 失败: {}", e);
         }
-        
-        println!("接收器状态: {}", 
-            if handler.is_receiving() { "运行中" } else { "已停止" });
-        
-        if let Some(port) = handler.receive_port() {
-            println!("接收端口: {}", port);
+    }
+
+    /// 处理流量统计命令
+    fn handle_stats(&self, handler: &UdpMessageHandler) {
+        let stats = handler.stats();
+        println!("=== 流量统计 ===");
+        println!("统计开始时间: {}", stats.started_at.format("%Y-%m-%d %H:%M:%S"));
+        println!("已发送: {} 个报文 / {} 字节", stats.packets_sent, stats.bytes_sent);
+        println!("已接收: {} 个报文 / {} 字节", stats.packets_received, stats.bytes_received);
+        println!("解码失败: {}", stats.decode_failures);
+        println!("写入错误: {}", stats.write_errors);
+        println!("接收错误: {}", stats.receive_errors);
+        println!("推送丢弃: {} (订阅者队列已满)", stats.subscriber_drops);
+
+        if stats.peers.is_empty() {
+            return;
         }
-        
-        println!("消息保存路径: {}", handler.output_file().display());
-        
-        // 显示 frp 状态
-        println!("\n=== Frp 内网穿透状态 ===");
-        println!("运行状态: {}", if handler.is_frp_running() { "运行中" } else { "已停止" });
-        
-        if let Some(status) = handler.get_frp_status() {
-            println!("服务器地址: {}:{}", status.config.server_addr, status.config.server_port);
-            println!("本地端口: {}", status.config.local_port);
-            println!("协议: {}", status.config.protocol);
-            println!("代理名称: {}", status.config.name);
-        } else {
-            println!("Frp 未初始化");
+        println!("\n=== 对端统计 ===");
+        let mut peers: Vec<_> = stats.peers.iter().collect();
+        peers.sort_by_key(|(addr, _)| **addr);
+        for (addr, peer) in peers {
+            println!("{}: 发送 {} 个 / {} 字节, 接收 {} 个 / {} 字节",
+                addr, peer.packets_sent, peer.bytes_sent, peer.packets_received, peer.bytes_received);
+        }
+        let other = &stats.other_peers;
+        if other.evicted > 0 {
+            println!("其他 (已淘汰 {} 次): 发送 {} 个 / {} 字节, 接收 {} 个 / {} 字节",
+                other.evicted, other.stats.packets_sent, other.stats.bytes_sent,
+                other.stats.packets_received, other.stats.bytes_received);
+        }
+    }
+
+    /// 处理 frp 代理管理命令
+    fn handle_frp_proxy(&self, handler: &mut UdpMessageHandler, args: &[&str]) {
+        match args.first().copied().unwrap_or("list") {
+            "list" => {
+                let mut out = String::new();
+                write_proxy_list(&mut out, &handler.frp_proxies());
+                print!("{}", out);
+            }
+            "add" => match ProxyConfig::from_args(&args[1..]) {
+                Ok(proxy) => {
+                    let name = proxy.name.clone();
+                    match handler.add_frp_proxy(proxy) {
+                        Ok(()) => println!("已添加代理 {}", name),
+                        Err(e) => eprintln!("添加代理失败: {:#}", e),
+                    }
+                }
+                Err(e) => eprintln!("{:#}", e),
+            },
+            "rm" => match args.get(1) {
+                Some(name) => match handler.remove_frp_proxy(name) {
+                    Ok(()) => println!("已删除代理 {}", name),
+                    Err(e) => eprintln!("删除代理失败: {:#}", e),
+                },
+                None => println!("用法: frp proxy rm <名称>"),
+            },
+            other => println!("未知的 frp proxy 命令: {} (可用: list、add、rm)", other),
+        }
+    }
+
+    /// 处理 frp visitor 管理命令
+    fn handle_frp_visitor(&self, handler: &mut UdpMessageHandler, args: &[&str]) {
+        match args.first().copied().unwrap_or("list") {
+            "list" => {
+                let mut out = String::new();
+                write_visitor_list(&mut out, &handler.frp_visitors());
+                print!("{}", out);
+            }
+            "add" => match VisitorConfig::from_args(&args[1..]) {
+                Ok(visitor) => {
+                    let name = visitor.name.clone();
+                    match handler.add_frp_visitor(visitor) {
+                        Ok(()) => println!("已添加 visitor {}", name),
+                        Err(e) => eprintln!("添加 visitor 失败: {:#}", e),
+                    }
+                }
+                Err(e) => eprintln!("{:#}", e),
+            },
+            "rm" => match args.get(1) {
+                Some(name) => match handler.remove_frp_visitor(name) {
+                    Ok(()) => println!("已删除 visitor {}", name),
+                    Err(e) => eprintln!("删除 visitor 失败: {:#}", e),
+                },
+                None => println!("用法: frp visitor rm <名称>"),
+            },
+            "share" => {
+                let bind_port = match args.get(2).map(|p| p.parse::<u16>()) {
+                    Some(Ok(port)) => Some(port),
+                    Some(Err(_)) => {
+                        eprintln!("无效的端口号: {}", args[2]);
+                        return;
+                    }
+                    None => None,
+                };
+                match args.get(1) {
+                    Some(name) => match handler.frp_share_report(name, bind_port) {
+                        Ok(report) => print!("{}", report),
+                        Err(e) => eprintln!("{:#}", e),
+                    },
+                    None => println!("用法: {}", FRP_SHARE_USAGE),
+                }
+            }
+            other => println!("未知的 frp visitor 命令: {} (可用: list、add、rm、share)", other),
+        }
+    }
+
+    /// 显示联系人列表
+    fn handle_contacts(&self, handler: &UdpMessageHandler) {
+        if handler.contacts().is_empty() {
+            println!("没有联系人 (可在配置文件的 [contacts] 中添加)");
+            return;
+        }
+        println!("=== 联系人 ===");
+        let mut contacts: Vec<_> = handler.contacts().iter().collect();
+        contacts.sort();
+        for (name, addr) in contacts {
+            println!("{}: {}", name, addr);
         }
     }
 
@@ -446,11 +1355,33 @@ impl InputHandler {
     }
     
     /// 处理 frp 相关命令
-    fn handle_frp(&self, handler: &mut UdpMessageHandler, args: &[&str]) {
+    fn handle_frp(&self, shared: &SharedHandler, args: &[&str]) {
         if args.is_empty() {
             self.show_frp_help();
             return;
         }
+        match args[0] {
+            "status" => {
+                print!("{}", UdpMessageHandler::frp_status_report(shared));
+                return;
+            }
+            "version" => {
+                // 运行 frpc -v 时不持有锁
+                let (located, tools_dir) = {
+                    let handler = shared.lock().unwrap();
+                    (handler.locate_frpc(), handler.frp_paths().tools_dir.clone())
+                };
+                print!("{}", frp_download::version_report(located, &tools_dir));
+                return;
+            }
+            "download" => {
+                self.handle_frp_download(shared, &args[1..]);
+                return;
+            }
+            _ => {}
+        }
+        let mut handler = shared.lock().unwrap();
+        let handler = &mut *handler;
         
         match args[0] {
             "init" => {
@@ -468,34 +1399,53 @@ impl InputHandler {
                     eprintln!("停止 frp 失败: {}", e);
                 }
             }
-            "status" => {
-                self.handle_frp_status(handler);
-            }
             "config" => {
-                if args.len() < 3 {
-                    println!("用法: frp config <服务器地址> <端口> [token]");
+                let (args, protocol) = match split_type_option(&args[1..]) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                };
+                if args.len() < 2 {
+                    println!("用法: {}", FRP_CONFIG_USAGE);
                     return;
                 }
-                let server_addr = args[1];
-                let server_port = match args[2].parse::<u16>() {
+                let server_addr = args[0];
+                let server_port = match args[1].parse::<u16>() {
                     Ok(port) => port,
                     Err(_) => {
-                        eprintln!("无效的端口号: {}", args[2]);
+                        eprintln!("无效的端口号: {}", args[1]);
                         return;
                     }
                 };
-                let token = args.get(3).copied();
+                let token = args.get(2).copied();
                 
-                if let Err(e) = handler.configure_frp(server_addr, server_port, token) {
+                if let Err(e) = handler.configure_frp(server_addr, server_port, token, protocol) {
                     eprintln!("配置 frp 失败: {}", e);
                 }
             }
-            "download" => {
-                println!("正在下载 frp 客户端...");
-                // 由于 download_frp 是异步方法，我们需要在运行时处理
-                // 这里简化处理，提示用户手动下载
-                println!("请手动下载 frp 客户端并放置在当前目录");
-                println!("下载地址: https://github.com/fatedier/frp/releases");
+            "proxy" => self.handle_frp_proxy(handler, &args[1..]),
+            "visitor" => self.handle_frp_visitor(handler, &args[1..]),
+            "set" => match args.get(1..) {
+                Some([key, value]) => match handler.set_frp_option(key, value) {
+                    Ok(()) => println!("已设置 {} = {}", key, value),
+                    Err(e) => eprintln!("设置 frp 选项失败: {:#}", e),
+                },
+                _ => println!("用法: {} (可用选项: {})", FRP_SET_USAGE, frp::FRP_OPTIONS.join(", ")),
+            },
+            "load" => {
+                let (verbatim, path) = match args.get(1..) {
+                    Some(["--raw", path]) => (true, *path),
+                    Some([path]) => (false, *path),
+                    _ => {
+                        println!("用法: frp load [--raw] <frpc.toml 路径>");
+                        return;
+                    }
+                };
+                if let Err(e) = handler.load_frp_config(Path::new(path), verbatim) {
+                    eprintln!("加载 frp 配置失败: {:#}", e);
+                }
             }
             _ => {
                 println!("未知的 frp 命令: {}", args[0]);
@@ -504,36 +1454,58 @@ impl InputHandler {
         }
     }
     
-    /// 显示 frp 状态
-    fn handle_frp_status(&self, handler: &UdpMessageHandler) {
-        println!("=== Frp 状态 ===");
-        println!("运行状态: {}", if handler.is_frp_running() { "运行中" } else { "已停止" });
-        
-        if let Some(status) = handler.get_frp_status() {
-            println!("服务器地址: {}:{}", status.config.server_addr, status.config.server_port);
-            println!("本地端口: {}", status.config.local_port);
-            println!("协议: {}", status.config.protocol);
-            println!("代理名称: {}", status.config.name);
-            if let Some(ref token) = status.config.token {
-                println!("认证令牌: {}", token);
-            }
-            println!("配置文件: {}", status.config_path.display());
-        } else {
-            println!("Frp 未初始化");
+    /// 下载 frp 客户端
+    ///
+    /// 只在读取托管目录和记录下载结果时加锁，下载期间其他服务可以继续使用处理器。
+    fn handle_frp_download(&self, shared: &SharedHandler, args: &[&str]) {
+        let defaults = DownloadOptions {
+            tools_dir: shared.lock().unwrap().frp_paths().tools_dir.clone(),
+            ..DownloadOptions::default()
+        };
+        let options = match parse_download_args(args, defaults) {
+            Ok(options) => options,
+            Err(e) => {
+                eprintln!("{}", e);
+                println!("用法: {}", FRP_DOWNLOAD_USAGE);
+                return;
+            }
+        };
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                eprintln!("创建下载运行时失败: {}", e);
+                return;
+            }
+        };
+        match runtime.block_on(frp_download::download_frpc(&options)) {
+            Ok(frpc_path) => shared.lock().unwrap().use_downloaded_frpc(frpc_path),
+            Err(e) => {
+                eprintln!("下载 frp 失败: {:#}", e);
+                println!("可以手动下载 frp 客户端并放置在当前目录: https://github.com/fatedier/frp/releases");
+            }
         }
     }
-    
+
     /// 显示 frp 帮助信息
     fn show_frp_help(&self) {
         println!("\n=== Frp 内网穿透命令 ===");
         println!("  frp init     - 初始化 frp 管理器");
-        println!("  frp config   - 配置 frp 服务器 (用法: frp config <服务器地址> <端口> [token])");
+        println!("  frp config   - 配置 frp 服务器 (用法: {})", FRP_CONFIG_USAGE);
         println!("  frp start    - 启动 frp 内网穿透");
         println!("  frp stop     - 停止 frp 内网穿透");
         println!("  frp status   - 显示 frp 状态");
-        println!("  frp download - 下载 frp 客户端");
+        println!("  frp proxy    - 管理代理 (用法: frp proxy list | rm <名称> | {})", frp::PROXY_ADD_USAGE.trim_start_matches("frp proxy "));
+        println!("  frp visitor  - 管理 visitor (用法: frp visitor list | rm <名称> | {} | {})",
+            frp::VISITOR_ADD_USAGE.trim_start_matches("frp visitor "),
+            FRP_SHARE_USAGE.trim_start_matches("frp visitor "));
+        println!("  frp set      - 设置客户端选项 (用法: {}，可用选项: {})", FRP_SET_USAGE, frp::FRP_OPTIONS.join(", "));
+        println!("  frp load     - 加载已有的 frpc 配置文件 (用法: frp load [--raw] <路径>，--raw 表示原样使用该文件)");
+        println!("  frp download - 下载并校验 frp 客户端 (用法: {})", FRP_DOWNLOAD_USAGE);
+        println!("  frp version  - 显示当前使用的 frpc 版本和已安装的版本");
         println!("\n示例:");
         println!("  frp config frp.example.com 7000 mytoken");
+        println!("  frp config frp.example.com 7000 mytoken --type udp");
+        println!("  frp config frp.example.com 7000 env:FRP_TOKEN");
         println!("  frp init");
         println!("  frp start");
     }
@@ -545,7 +1517,10 @@ impl InputHandler {
         println!("  start  - 启动消息接收器");
         println!("  stop   - 停止消息接收器");
         println!("  status - 显示当前状态");
+        println!("  stats  - 显示流量统计");
         println!("  version - 显示当前版本");
+        println!("  ping   - 测量到对端的延迟 (用法: ping <IP:端口> [次数])");
+        println!("  contacts - 显示联系人");
         println!("  frp    - Frp 内网穿透管理 (输入 'frp' 查看详细帮助)");
         println!("  quit   - 退出程序");
         println!("  help   - 显示此帮助信息");
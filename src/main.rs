@@ -1,5 +1,16 @@
+#![allow(non_snake_case)]
+
 use std::io::{self, Write};
-use NChat::{InputHandler,UdpMessageHandler};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use clap::{Parser, Subcommand};
+use NChat::{InputHandler,SharedHandler,UdpMessageHandler};
+use NChat::{api, bot, metrics, ping, script, webhook, ws};
+use NChat::config::{self, Settings};
 
 // mod newchat {
 //     pub use crate::*;
@@ -8,9 +19,214 @@ use NChat::{InputHandler,UdpMessageHandler};
 // 默认输出文件
 const DEFAULT_OUTPUT_FILE: &str = "received_messages.log";
 
+/// NChat 命令行参数
+#[derive(Parser)]
+#[command(name = "nchat", about = "UDP 消息收发程序")]
+struct Cli {
+    /// 配置文件路径（默认依次查找 ./nchat.toml 和用户配置目录下的 nchat/nchat.toml）
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// 使用配置文件中的指定 profile
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// 在指定地址开启 Prometheus 指标服务 (例如 127.0.0.1:9100)
+    #[arg(long, value_name = "ADDR")]
+    metrics: Option<SocketAddr>,
+
+    /// 在指定地址开启本地 HTTP API (例如 127.0.0.1:8787)
+    #[arg(long, value_name = "ADDR")]
+    api: Option<SocketAddr>,
+
+    /// 在指定地址开启 WebSocket 桥接服务，供浏览器客户端收发消息 (例如 127.0.0.1:8790)
+    #[arg(long, value_name = "ADDR")]
+    ws: Option<SocketAddr>,
+
+    /// 把接收到的消息以 JSON 形式 POST 到该地址
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// 只转发来自该对端的消息 (IP 或 IP:端口，可重复)
+    #[arg(long = "webhook-peer", value_name = "PEER", requires = "webhook")]
+    webhook_peers: Vec<String>,
+
+    /// 只转发包含该关键字的消息 (可重复)
+    #[arg(long = "webhook-keyword", value_name = "KEYWORD", requires = "webhook")]
+    webhook_keywords: Vec<String>,
+
+    /// 开启回显机器人：把收到的消息原样回复给发送方
+    #[arg(long, conflicts_with = "auto_reply")]
+    echo: bool,
+
+    /// 开启自动回复机器人：对收到的每条消息回复指定内容
+    #[arg(long, value_name = "TEXT")]
+    auto_reply: Option<String>,
+
+    /// 每收到一条消息执行一次该 shell 命令（消息字段见 NCHAT_* 环境变量，内容同时写入标准输入）
+    #[arg(long, value_name = "CMD")]
+    exec: Option<String>,
+
+    /// 外部命令的最大并发数（默认 4）
+    #[arg(long, value_name = "N")]
+    exec_concurrency: Option<usize>,
+
+    /// 外部命令的超时时间（秒，默认 30）
+    #[arg(long, value_name = "SECS")]
+    exec_timeout: Option<u64>,
+
+    /// 守护进程控制套接字路径（默认为 $XDG_RUNTIME_DIR/nchat.sock 或系统临时目录下的 nchat-<uid>/nchat.sock）
+    #[arg(long, value_name = "PATH", global = true)]
+    socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// 非交互式子命令（不带子命令时进入交互模式）
+#[derive(Subcommand)]
+enum Commands {
+    /// 测量到对端 NChat 接收器的往返延迟
+    Ping {
+        /// 对端地址 (IP:端口 或联系人名称)
+        peer: String,
+        /// 发送次数
+        #[arg(default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+    },
+    /// 以守护进程模式运行接收器和 frp，并通过控制套接字接受命令
+    Daemon {
+        /// 启动时开启接收器的端口（默认使用配置文件中的 receive_port）
+        #[arg(long)]
+        port: Option<u16>,
+        /// 启动时初始化并启动 frp（也可在配置文件的 [autostart] 中设置 frp = true）
+        #[arg(long)]
+        frp: bool,
+    },
+    /// 通过正在运行的守护进程发送消息
+    Send {
+        /// 目标地址 (IP:端口 或联系人名称)
+        target: String,
+        /// 消息内容
+        #[arg(required = true, trailing_var_arg = true)]
+        message: Vec<String>,
+    },
+    /// 显示正在运行的守护进程的状态
+    Status,
+    /// 控制正在运行的守护进程中的 frp (init/start/stop/status/config)
+    Frp {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// 停止正在运行的守护进程
+    Shutdown,
+}
+
 fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let (settings, config_path) =
+        config::load_settings(cli.config.as_deref(), cli.profile.as_deref()).map_err(to_io_error)?;
+    if cli.command.is_none() {
+        if let Some(ref path) = config_path {
+            println!("已加载配置文件: {}", path.display());
+        }
+    }
+
+    match cli.command {
+        Some(Commands::Ping { ref peer, count }) => {
+            let peer = settings.contacts.get(peer).unwrap_or(peer);
+            ping::ping(peer, count, ping::DEFAULT_PING_INTERVAL, ping::DEFAULT_PING_TIMEOUT)?;
+            Ok(())
+        }
+        Some(Commands::Daemon { port, frp }) => run_daemon(&cli, &settings, port, frp),
+        Some(Commands::Send { ref target, ref message }) => {
+            control(&cli, &settings, &format!("send {} {}", target, message.join(" ")))
+        }
+        Some(Commands::Status) => control(&cli, &settings, "status"),
+        Some(Commands::Frp { ref args }) => control(&cli, &settings, &format!("frp {}", args.join(" "))),
+        Some(Commands::Shutdown) => control(&cli, &settings, "shutdown"),
+        None => run_interactive(&cli, &settings),
+    }
+}
+
+/// 消息保存路径：配置文件中的 log_path，未设置时使用默认文件
+fn output_file(settings: &Settings) -> &str {
+    settings.log_path.as_deref().unwrap_or(DEFAULT_OUTPUT_FILE)
+}
+
+/// 按配置创建消息处理器
+fn build_handler(settings: &Settings) -> io::Result<SharedHandler> {
+    let mut handler = UdpMessageHandler::new(output_file(settings))?;
+    if let Some(addr) = settings.bind_address {
+        handler.set_bind_address(addr);
+    }
+    if let Some(format) = settings.log_format {
+        handler.set_log_format(format);
+    }
+    handler.set_nickname(settings.nickname.clone());
+    handler.set_contacts(settings.contacts.clone());
+    handler.set_frp_defaults(settings.frp.clone());
+    if let Some(ref paths) = settings.frp_paths {
+        handler.set_frp_paths(paths.clone());
+    }
+    Ok(Arc::new(Mutex::new(handler)))
+}
+
+/// 控制套接字路径：命令行参数优先，其次是配置文件
+#[cfg(unix)]
+fn socket_path(cli: &Cli, settings: &Settings) -> PathBuf {
+    cli.socket
+        .clone()
+        .or_else(|| settings.socket.clone())
+        .unwrap_or_else(NChat::daemon::default_socket_path)
+}
+
+/// 守护进程模式
+#[cfg(unix)]
+fn run_daemon(cli: &Cli, settings: &Settings, port: Option<u16>, frp: bool) -> io::Result<()> {
+    use NChat::daemon::{self, DaemonOptions};
+
+    let handler = build_handler(settings)?;
+    start_services(cli, settings, &handler)?;
+    let options = DaemonOptions {
+        socket_path: socket_path(cli, settings),
+        receive_port: port.or(settings.receive_port),
+        start_frp: frp || settings.autostart.as_ref().is_some_and(|a| a.frp),
+    };
+    daemon::run_daemon(handler, options)
+}
+
+#[cfg(not(unix))]
+fn run_daemon(_cli: &Cli, _settings: &Settings, _port: Option<u16>, _frp: bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "守护进程模式仅支持 Unix 系统"))
+}
+
+/// 把命令发送给正在运行的守护进程并输出结果
+#[cfg(unix)]
+fn control(cli: &Cli, settings: &Settings, command: &str) -> io::Result<()> {
+    use NChat::daemon;
+
+    match daemon::send_control_command(&socket_path(cli, settings), command)? {
+        Ok(body) => {
+            print!("{}", body);
+            Ok(())
+        }
+        Err(msg) => {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn control(_cli: &Cli, _settings: &Settings, _command: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "守护进程模式仅支持 Unix 系统"))
+}
+
+/// 交互模式
+fn run_interactive(cli: &Cli, settings: &Settings) -> io::Result<()> {
     println!("UDP 消息收发程序");
-    println!("消息将保存到: {}", DEFAULT_OUTPUT_FILE);
+    println!("消息将保存到: {}", output_file(settings));
 
     // 添加 Windows 特定提示
     if cfg!(windows) {
@@ -18,11 +234,27 @@ fn main() -> io::Result<()> {
     }
 
     // 创建消息处理器
-    let mut handler = UdpMessageHandler::new(DEFAULT_OUTPUT_FILE)?;
+    let handler = build_handler(settings)?;
     let input_handler = InputHandler;
-    
+
+    let status_tx = start_services(cli, settings, &handler)?;
+    handler.lock().unwrap().set_frp_status_sender(status_tx.clone());
+
+    // 按配置自动启动接收器和 frp，失败时只报告错误，不退出交互模式
+    if let Some(ref autostart) = settings.autostart {
+        let errors = handler.lock().unwrap().autostart(
+            settings.receive_port,
+            autostart.receiver,
+            autostart.frp,
+            status_tx,
+        );
+        for e in errors {
+            eprintln!("{}", e);
+        }
+    }
+
     // 显示初始状态
-    println!("发送端口: {}", handler.local_send_port()?);
+    println!("发送端口: {}", handler.lock().unwrap().local_send_port()?);
     input_handler.show_help();
 
     loop {
@@ -32,14 +264,99 @@ fn main() -> io::Result<()> {
 
         // 读取用户命令
         let mut command = String::new();
-        io::stdin().read_line(&mut command)?;
+        if io::stdin().read_line(&mut command)? == 0 {
+            // 标准输入已关闭
+            break;
+        }
         let command = command.trim();
-        
+
         // 处理命令
-        if input_handler.handle_command(command, &mut handler) {
+        if input_handler.handle_command(command, &handler) {
             break;
         }
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 按命令行参数注册消息钩子，启动指标服务、HTTP API、WebSocket 桥接、webhook 和脚本钩子，
+/// 并在后台打印它们的状态信息。命令行未指定的服务使用配置文件中的设置。
+/// 返回的发送端可供其他后台任务继续报告状态。
+fn start_services(cli: &Cli, settings: &Settings, handler: &SharedHandler) -> io::Result<Sender<String>> {
+    if cli.echo {
+        handler.lock().unwrap().add_hook(bot::EchoBot);
+        println!("回显机器人已开启");
+    } else if let Some(ref reply) = cli.auto_reply {
+        handler.lock().unwrap().add_hook(bot::AutoReplyBot::new(reply));
+        println!("自动回复机器人已开启");
+    }
+
+    let (status_tx, status_rx) = channel::<String>();
+    if let Some(addr) = cli.metrics.or(settings.metrics) {
+        let source = handler.lock().unwrap().metrics_source();
+        metrics::spawn_metrics_server(addr, source, status_tx.clone())?;
+    }
+    let api_addr = cli.api.or(settings.api);
+    let ws_addr = cli.ws.or(settings.ws);
+    let token = match settings.api_token {
+        Some(ref token) => token.clone(),
+        None => NChat::frp::generate_secret_key(),
+    };
+    if settings.api_token.is_none() && (api_addr.is_some() || ws_addr.is_some()) {
+        println!("HTTP API 和 WebSocket 访问令牌: {} (可在配置文件中用 api_token 固定)", token);
+    }
+    if let Some(addr) = api_addr {
+        api::spawn_api_server(addr, token.clone(), handler.clone(), status_tx.clone())?;
+    }
+    if let Some(addr) = ws_addr {
+        let auth = ws::WsAuth { token, allowed_origins: settings.ws_origins.clone() };
+        let stats = handler.lock().unwrap().traffic_stats();
+        ws::spawn_ws_server(addr, auth, handler.clone(), stats, status_tx.clone())?;
+    }
+    let webhook_settings = match cli.webhook {
+        Some(ref url) => Some(config::WebhookSettings {
+            url: url.clone(),
+            peers: cli.webhook_peers.clone(),
+            keywords: cli.webhook_keywords.clone(),
+        }),
+        None => settings.webhook.clone(),
+    };
+    if let Some(webhook_settings) = webhook_settings {
+        let mut config = webhook::WebhookConfig::new(&webhook_settings.url);
+        for peer in &webhook_settings.peers {
+            config.peers.push(webhook::PeerFilter::parse(peer).map_err(to_io_error)?);
+        }
+        config.keywords = webhook_settings.keywords;
+        let messages = handler.lock().unwrap().subscribe(config.queue_capacity);
+        webhook::spawn_webhook(config, messages, status_tx.clone()).map_err(to_io_error)?;
+    }
+    let exec_settings = match cli.exec {
+        Some(ref command) => Some(config::ExecSettings {
+            command: command.clone(),
+            concurrency: None,
+            timeout: None,
+        }),
+        None => settings.exec.clone(),
+    };
+    if let Some(exec_settings) = exec_settings {
+        let mut config = script::ScriptHookConfig::new(&exec_settings.command);
+        if let Some(n) = cli.exec_concurrency.or(exec_settings.concurrency) {
+            config.max_concurrency = n;
+        }
+        if let Some(secs) = cli.exec_timeout.or(exec_settings.timeout) {
+            config.timeout = Duration::from_secs(secs);
+        }
+        let messages = handler.lock().unwrap().subscribe(config.queue_capacity);
+        script::spawn_script_hook(config, messages, status_tx.clone());
+    }
+    thread::spawn(move || {
+        for msg in status_rx {
+            println!("{}", msg);
+        }
+    });
+    Ok(status_tx)
+}
+
+fn to_io_error(e: anyhow::Error) -> io::Error {
+    io::Error::other(format!("{:#}", e))
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+use chrono::{DateTime, Local};
+
+/// 默认保留的最近消息条数
+pub const DEFAULT_RECENT_CAPACITY: usize = 200;
+
+/// 接收到的一条消息
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    pub timestamp: DateTime<Local>,
+    pub source: SocketAddr,
+    /// 消息文本（非 UTF-8 数据为 `<BINARY DATA: n bytes>`）
+    pub text: String,
+    /// 原始报文字节数
+    pub size: usize,
+    /// 是否为无法解码的二进制数据
+    pub binary: bool,
+}
+
+impl ReceivedMessage {
+    /// 转换为 JSON 对象
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "source": self.source.to_string(),
+            "message": self.text,
+            "size": self.size,
+            "binary": self.binary,
+        })
+    }
+}
+
+/// 最近接收消息的环形缓冲区
+#[derive(Debug)]
+pub struct RecentMessages {
+    capacity: usize,
+    messages: Mutex<VecDeque<ReceivedMessage>>,
+}
+
+impl RecentMessages {
+    /// 创建指定容量的缓冲区
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            messages: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// 追加一条消息，超出容量时丢弃最旧的消息
+    pub fn push(&self, message: ReceivedMessage) {
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() == self.capacity {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    /// 获取最近的 `limit` 条消息（按时间顺序）
+    pub fn latest(&self, limit: usize) -> Vec<ReceivedMessage> {
+        let messages = self.messages.lock().unwrap();
+        let skip = messages.len().saturating_sub(limit);
+        messages.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// 接收消息的订阅者列表
+///
+/// 每个订阅者使用有界队列，接收线程只做非阻塞投递，
+/// 处理缓慢的订阅者会丢失消息而不会阻塞接收线程。
+#[derive(Debug, Default)]
+pub struct MessageSubscribers {
+    senders: Mutex<Vec<SyncSender<ReceivedMessage>>>,
+}
+
+impl MessageSubscribers {
+    /// 新增一个订阅者，队列最多缓存 `capacity` 条消息
+    pub fn subscribe(&self, capacity: usize) -> Receiver<ReceivedMessage> {
+        let (tx, rx) = sync_channel(capacity);
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// 向所有订阅者投递消息，返回因队列已满而丢弃的数量
+    ///
+    /// 已断开的订阅者会被移除。
+    pub fn publish(&self, message: &ReceivedMessage) -> usize {
+        let mut dropped = 0;
+        self.senders.lock().unwrap().retain(|tx| match tx.try_send(message.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                dropped += 1;
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+        dropped
+    }
+}
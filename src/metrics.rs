@@ -0,0 +1,122 @@
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use tokio::net::TcpListener;
+
+use crate::frp::FrpMetrics;
+use crate::http::{self, HttpResponse};
+use crate::stats::TrafficStats;
+
+/// Prometheus 文本格式的 Content-Type
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 指标数据来源（全部为共享引用，导出时无需锁住消息处理器）
+#[derive(Clone)]
+pub struct MetricsSource {
+    pub stats: Arc<TrafficStats>,
+    pub receiver_running: Arc<AtomicBool>,
+    pub frp: Arc<FrpMetrics>,
+}
+
+impl MetricsSource {
+    /// 以 Prometheus 文本格式输出所有指标
+    pub fn render(&self) -> String {
+        let stats = self.stats.snapshot();
+        let mut out = String::new();
+
+        write_metric(&mut out, "nchat_messages_sent_total", "counter",
+            "Datagrams sent since start.", stats.packets_sent);
+        write_metric(&mut out, "nchat_messages_received_total", "counter",
+            "Datagrams received since start.", stats.packets_received);
+        write_metric(&mut out, "nchat_bytes_sent_total", "counter",
+            "Bytes sent since start.", stats.bytes_sent);
+        write_metric(&mut out, "nchat_bytes_received_total", "counter",
+            "Bytes received since start.", stats.bytes_received);
+        write_metric(&mut out, "nchat_decode_failures_total", "counter",
+            "Received datagrams that were not valid UTF-8.", stats.decode_failures);
+        write_metric(&mut out, "nchat_write_errors_total", "counter",
+            "Errors writing received messages to the log file.", stats.write_errors);
+        write_metric(&mut out, "nchat_receive_errors_total", "counter",
+            "Socket errors in the receiver thread.", stats.receive_errors);
+        write_metric(&mut out, "nchat_subscriber_drops_total", "counter",
+            "Received messages dropped by slow subscribers (WebSocket, webhook, exec).", stats.subscriber_drops);
+        write_metric(&mut out, "nchat_receiver_up", "gauge",
+            "Whether the UDP receiver is running.",
+            self.receiver_running.load(Ordering::SeqCst) as u64);
+        write_metric(&mut out, "nchat_frp_running", "gauge",
+            "Whether the frpc process is running.", self.frp.is_running() as u64);
+        write_metric(&mut out, "nchat_frp_starts_total", "counter",
+            "Times the frpc process was started.", self.frp.starts());
+        write_metric(&mut out, "nchat_frp_restarts_total", "counter",
+            "Times the frpc process was started again after the first start.", self.frp.restarts());
+        write_metric(&mut out, "nchat_start_time_seconds", "gauge",
+            "Unix time when statistics collection started.", stats.started_at.timestamp() as u64);
+
+        out
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// 在后台线程中启动指标 HTTP 服务（GET /metrics）
+///
+/// 端口绑定在当前线程中完成，绑定失败会直接返回错误。
+pub fn spawn_metrics_server(
+    addr: SocketAddr,
+    source: MetricsSource,
+    status_sender: Sender<String>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let handle = thread::spawn(move || {
+        runtime.block_on(async move {
+            let listener = match TcpListener::from_std(listener) {
+                Ok(l) => l,
+                Err(e) => {
+                    let _ = status_sender.send(format!("指标服务启动失败: {}", e));
+                    return;
+                }
+            };
+            let _ = status_sender.send(format!("指标服务已启动: http://{}/metrics", addr));
+
+            loop {
+                let (mut stream, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        let _ = status_sender.send(format!("指标服务接受连接失败: {}", e));
+                        continue;
+                    }
+                };
+                let source = source.clone();
+                tokio::spawn(async move {
+                    let response = match http::read_request(&mut stream).await {
+                        Ok(req) if req.path == "/metrics" && req.method == "GET" => {
+                            HttpResponse::text(200, PROMETHEUS_CONTENT_TYPE, source.render())
+                        }
+                        Ok(req) if req.path == "/metrics" => {
+                            HttpResponse::text(405, "text/plain; charset=utf-8", "method not allowed\n")
+                        }
+                        Ok(_) => HttpResponse::not_found(),
+                        Err(e) => HttpResponse::text(
+                            http::error_status(&e), "text/plain; charset=utf-8", format!("{}\n", e)),
+                    };
+                    let _ = response.write_to(&mut stream).await;
+                });
+            }
+        });
+    });
+
+    Ok(handle)
+}
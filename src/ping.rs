@@ -0,0 +1,248 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// 回显请求报文前缀（以 0 字节开头，避免与普通文本消息混淆）
+pub const ECHO_REQUEST_PREFIX: &[u8] = b"\x00NCHAT-ECHO-REQ ";
+/// 回显应答报文前缀
+pub const ECHO_REPLY_PREFIX: &[u8] = b"\x00NCHAT-ECHO-REP ";
+
+/// 默认单次等待应答的超时时间
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(1);
+/// 默认发送间隔
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 如果报文是回显请求，则生成对应的回显应答
+pub fn echo_reply(datagram: &[u8]) -> Option<Vec<u8>> {
+    let payload = datagram.strip_prefix(ECHO_REQUEST_PREFIX)?;
+    let mut reply = Vec::with_capacity(ECHO_REPLY_PREFIX.len() + payload.len());
+    reply.extend_from_slice(ECHO_REPLY_PREFIX);
+    reply.extend_from_slice(payload);
+    Some(reply)
+}
+
+/// 从回显应答中解析序号
+fn parse_reply_seq(datagram: &[u8]) -> Option<u32> {
+    let payload = datagram.strip_prefix(ECHO_REPLY_PREFIX)?;
+    std::str::from_utf8(payload).ok()?.trim().parse().ok()
+}
+
+/// 延迟测量结果
+#[derive(Debug, Clone)]
+pub struct PingReport {
+    pub target: SocketAddr,
+    pub sent: u32,
+    pub rtts: Vec<Duration>,
+}
+
+impl PingReport {
+    /// 收到应答的数量
+    pub fn received(&self) -> u32 {
+        self.rtts.len() as u32
+    }
+
+    /// 丢包率（百分比）
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (self.sent - self.received()) as f64 * 100.0 / self.sent as f64
+    }
+
+    /// 最小往返时间
+    pub fn min(&self) -> Option<Duration> {
+        self.rtts.iter().min().copied()
+    }
+
+    /// 最大往返时间
+    pub fn max(&self) -> Option<Duration> {
+        self.rtts.iter().max().copied()
+    }
+
+    /// 平均往返时间
+    pub fn avg(&self) -> Option<Duration> {
+        if self.rtts.is_empty() {
+            return None;
+        }
+        Some(self.rtts.iter().sum::<Duration>() / self.rtts.len() as u32)
+    }
+
+    /// 抖动：相邻两次往返时间差的平均值
+    pub fn jitter(&self) -> Option<Duration> {
+        if self.rtts.len() < 2 {
+            return None;
+        }
+        let total: Duration = self
+            .rtts
+            .windows(2)
+            .map(|w| w[1].abs_diff(w[0]))
+            .sum();
+        Some(total / (self.rtts.len() - 1) as u32)
+    }
+
+    /// 打印统计摘要
+    pub fn print_summary(&self) {
+        println!("--- {} 延迟统计 ---", self.target);
+        println!(
+            "已发送 {} 个, 已接收 {} 个, 丢包率 {:.1}%",
+            self.sent,
+            self.received(),
+            self.loss_percent()
+        );
+        if let (Some(min), Some(avg), Some(max)) = (self.min(), self.avg(), self.max()) {
+            println!(
+                "往返时间 最小/平均/最大 = {:.3}/{:.3}/{:.3} ms",
+                as_millis(min),
+                as_millis(avg),
+                as_millis(max)
+            );
+        }
+        if let Some(jitter) = self.jitter() {
+            println!("抖动: {:.3} ms", as_millis(jitter));
+        }
+    }
+}
+
+fn as_millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// 向对端发送回显请求并测量往返时间
+///
+/// 对端需要运行 NChat 接收器，接收器会自动应答回显请求。
+pub fn ping(target: &str, count: u32, interval: Duration, timeout: Duration) -> io::Result<PingReport> {
+    let addr: SocketAddr = target.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("无效的目标地址格式: {} - {}", target, e),
+        )
+    })?;
+
+    // 使用独立的套接字，避免与消息发送端口上的数据混在一起
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let mut report = PingReport {
+        target: addr,
+        sent: 0,
+        rtts: Vec::new(),
+    };
+    let mut buf = [0; 256];
+
+    println!("PING {} ({} 次)", addr, count);
+    for seq in 1..=count {
+        let started = Instant::now();
+        let mut request = ECHO_REQUEST_PREFIX.to_vec();
+        request.extend_from_slice(seq.to_string().as_bytes());
+        socket.send_to(&request, addr)?;
+        report.sent += 1;
+
+        let mut answered = false;
+        while let Some(remaining) = timeout.checked_sub(started.elapsed()) {
+            socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            match socket.recv_from(&mut buf) {
+                Ok((size, source)) => {
+                    // 忽略迟到的旧应答和其他来源的数据
+                    if source != addr || parse_reply_seq(&buf[..size]) != Some(seq) {
+                        continue;
+                    }
+                    let rtt = started.elapsed();
+                    println!("来自 {} 的应答: 序号={} 时间={:.3} ms", addr, seq, as_millis(rtt));
+                    report.rtts.push(rtt);
+                    answered = true;
+                    break;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                e.kind() == io::ErrorKind::TimedOut => break,
+                // Windows 上对端端口不可达时会报告 ConnectionReset
+                Err(ref e) if e.kind() == io::ErrorKind::ConnectionReset => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if !answered {
+            println!("序号 {} 请求超时", seq);
+        }
+
+        if seq < count {
+            if let Some(wait) = interval.checked_sub(started.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+
+    report.print_summary();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn replies_only_to_echo_requests() {
+        let mut request = ECHO_REQUEST_PREFIX.to_vec();
+        request.extend_from_slice(b"7");
+        let reply = echo_reply(&request).unwrap();
+        assert!(reply.starts_with(ECHO_REPLY_PREFIX));
+        assert_eq!(parse_reply_seq(&reply), Some(7));
+
+        // 普通消息、应答报文和缺少 0 字节的前缀都不应答，避免两端互相回显
+        assert_eq!(echo_reply(b"hello"), None);
+        assert_eq!(echo_reply(&reply), None);
+        assert_eq!(echo_reply(&ECHO_REQUEST_PREFIX[1..]), None);
+        assert_eq!(echo_reply(b""), None);
+        assert_eq!(parse_reply_seq(&request), None);
+    }
+
+    #[test]
+    fn summarizes_round_trip_times() {
+        let report = PingReport {
+            target: "127.0.0.1:8080".parse().unwrap(),
+            sent: 4,
+            rtts: vec![ms(10), ms(30), ms(20)],
+        };
+        assert_eq!(report.received(), 3);
+        assert_eq!(report.loss_percent(), 25.0);
+        assert_eq!(report.min(), Some(ms(10)));
+        assert_eq!(report.max(), Some(ms(30)));
+        assert_eq!(report.avg(), Some(ms(20)));
+        // |30-10| 与 |20-30| 的平均值
+        assert_eq!(report.jitter(), Some(ms(15)));
+    }
+
+    #[test]
+    fn summarizes_lost_and_single_replies() {
+        let mut report = PingReport {
+            target: "127.0.0.1:8080".parse().unwrap(),
+            sent: 2,
+            rtts: Vec::new(),
+        };
+        assert_eq!(report.loss_percent(), 100.0);
+        assert_eq!((report.min(), report.avg(), report.max()), (None, None, None));
+        assert_eq!(report.jitter(), None);
+
+        report.rtts.push(ms(5));
+        assert_eq!(report.avg(), Some(ms(5)));
+        assert_eq!(report.jitter(), None);
+    }
+
+    #[test]
+    fn pings_local_echo_responder() {
+        let responder = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let target = responder.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let mut buf = [0; 256];
+            while let Ok((size, source)) = responder.recv_from(&mut buf) {
+                if let Some(reply) = echo_reply(&buf[..size]) {
+                    let _ = responder.send_to(&reply, source);
+                }
+            }
+        });
+
+        let report = ping(&target, 3, Duration::ZERO, DEFAULT_PING_TIMEOUT).unwrap();
+        assert_eq!(report.sent, 3);
+        assert_eq!(report.received(), 3);
+    }
+}
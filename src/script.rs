@@ -0,0 +1,233 @@
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{sync_channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::message::ReceivedMessage;
+
+/// 默认最大并发数
+pub const DEFAULT_SCRIPT_CONCURRENCY: usize = 4;
+/// 默认单次执行超时
+pub const DEFAULT_SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 默认等待执行的消息队列长度
+pub const DEFAULT_SCRIPT_QUEUE: usize = 100;
+/// 检查子进程是否结束的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 外部脚本钩子配置
+#[derive(Debug, Clone)]
+pub struct ScriptHookConfig {
+    /// 通过系统 shell 执行的命令
+    pub command: String,
+    pub max_concurrency: usize,
+    pub timeout: Duration,
+    pub queue_capacity: usize,
+}
+
+impl ScriptHookConfig {
+    /// 使用默认并发数、超时和队列长度创建配置
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            max_concurrency: DEFAULT_SCRIPT_CONCURRENCY,
+            timeout: DEFAULT_SCRIPT_TIMEOUT,
+            queue_capacity: DEFAULT_SCRIPT_QUEUE,
+        }
+    }
+}
+
+/// 在后台线程中为每条接收到的消息执行一次外部命令
+///
+/// 消息字段通过环境变量传递:
+/// `NCHAT_SOURCE`、`NCHAT_TIMESTAMP`、`NCHAT_MESSAGE`、`NCHAT_SIZE`、`NCHAT_BINARY`，
+/// 消息内容同时写入命令的标准输入。
+/// 执行失败、非零退出和超时都会通过状态通道报告。
+pub fn spawn_script_hook(
+    config: ScriptHookConfig,
+    messages: Receiver<ReceivedMessage>,
+    status_sender: Sender<String>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        // 并发令牌：取到令牌才能启动新进程，进程结束后归还
+        let max = config.max_concurrency.max(1);
+        let (token_tx, token_rx) = sync_channel::<()>(max);
+        for _ in 0..max {
+            let _ = token_tx.send(());
+        }
+
+        let _ = status_sender.send(format!("脚本钩子已启用: {}", config.command));
+        for message in messages {
+            if token_rx.recv().is_err() {
+                break;
+            }
+            let command = config.command.clone();
+            let timeout = config.timeout;
+            let status_sender = status_sender.clone();
+            let token_tx = token_tx.clone();
+            thread::spawn(move || {
+                if let Err(e) = run_script(&command, &message, timeout) {
+                    let _ = status_sender.send(format!(
+                        "脚本钩子执行失败 (来自 {} 的消息): {}",
+                        message.source, e
+                    ));
+                }
+                let _ = token_tx.send(());
+            });
+        }
+    })
+}
+
+/// 构造通过系统 shell 执行的命令
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        // 放入独立的进程组，超时时连同命令启动的子进程一起终止
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
+        }
+        cmd
+    }
+}
+
+/// 执行一次命令并等待结束，超时则终止进程
+fn run_script(command: &str, message: &ReceivedMessage, timeout: Duration) -> Result<(), String> {
+    let mut cmd = shell_command(command);
+    cmd.env("NCHAT_SOURCE", message.source.to_string())
+        .env("NCHAT_TIMESTAMP", message.timestamp.to_rfc3339())
+        .env("NCHAT_MESSAGE", &message.text)
+        .env("NCHAT_SIZE", message.size.to_string())
+        .env("NCHAT_BINARY", if message.binary { "1" } else { "0" })
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    let mut child = cmd.spawn().map_err(|e| format!("无法启动命令: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // 命令可能不读取标准输入，写入失败不视为错误
+        let _ = stdin.write_all(message.text.as_bytes());
+    }
+
+    wait_with_timeout(&mut child, timeout)
+}
+
+/// 终止命令及其启动的所有子进程
+///
+/// unix 上命令是所在进程组的组长，向整个进程组发送 SIGKILL；
+/// 只终止 shell 的话，`sleep 100; ...` 这类命令的子进程会继续运行。
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // SAFETY: 只向该命令所在的进程组发送信号
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    let _ = child.kill();
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<(), String> {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("命令退出状态: {}", status)),
+            Ok(None) if started.elapsed() >= timeout => {
+                kill_process_group(child);
+                let _ = child.wait();
+                return Err(format!("执行超时 ({} 秒)，已终止", timeout.as_secs()));
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(format!("等待命令结束失败: {}", e)),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc::channel;
+    use chrono::Local;
+
+    fn message(text: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            timestamp: Local::now(),
+            source: "127.0.0.1:9000".parse().unwrap(),
+            text: text.to_string(),
+            size: text.len(),
+            binary: false,
+        }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("nchat-script-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn timeout_kills_processes_started_by_command() {
+        let marker = temp_file("marker");
+        // 后台子进程在 shell 被终止后仍会运行，除非整个进程组被终止
+        let command = format!("(sleep 1; touch {}) & wait", marker.display());
+
+        let err = run_script(&command, &message("hi"), Duration::from_millis(200)).unwrap_err();
+        assert!(err.contains("执行超时"), "{}", err);
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn passes_message_and_reports_exit_status() {
+        let output = temp_file("output");
+        let command = format!("cat > {}; test \"$NCHAT_SOURCE\" = 127.0.0.1:9000", output.display());
+        run_script(&command, &message("你好"), DEFAULT_SCRIPT_TIMEOUT).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "你好");
+        let _ = fs::remove_file(&output);
+
+        let err = run_script("exit 3", &message("hi"), DEFAULT_SCRIPT_TIMEOUT).unwrap_err();
+        assert!(err.contains("命令退出状态"), "{}", err);
+    }
+
+    #[test]
+    fn runs_at_most_max_concurrency_commands() {
+        let log = temp_file("concurrency");
+        let mut config = ScriptHookConfig::new(&format!("echo s >> {0}; sleep 0.3; echo e >> {0}", log.display()));
+        config.max_concurrency = 2;
+        let (message_tx, message_rx) = channel();
+        let (status_tx, _status_rx) = channel();
+        for i in 0..6 {
+            message_tx.send(message(&i.to_string())).unwrap();
+        }
+        drop(message_tx);
+        spawn_script_hook(config, message_rx, status_tx).join().unwrap();
+
+        // 分发线程结束时最后几条命令可能仍在运行
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let lines = loop {
+            let content = fs::read_to_string(&log).unwrap_or_default();
+            if content.lines().count() == 12 || Instant::now() > deadline {
+                break content;
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let mut running = 0;
+        let mut max_running = 0;
+        for line in lines.lines() {
+            running += if line == "s" { 1 } else { -1 };
+            max_running = max_running.max(running);
+        }
+        assert_eq!(lines.lines().count(), 12);
+        assert_eq!(max_running, 2);
+        let _ = fs::remove_file(&log);
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use chrono::{DateTime, Local};
+
+/// 单独统计的对端数量上限，超出后最久未出现的对端并入“其他”
+pub const MAX_TRACKED_PEERS: usize = 256;
+
+/// 单个对端的流量计数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PeerStats {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+}
+
+impl PeerStats {
+    fn add(&mut self, other: &PeerStats) {
+        self.packets_sent += other.packets_sent;
+        self.bytes_sent += other.bytes_sent;
+        self.packets_received += other.packets_received;
+        self.bytes_received += other.bytes_received;
+    }
+}
+
+/// 被淘汰的对端的累计流量
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OtherPeers {
+    /// 并入的对端数量（同一对端再次被淘汰时重复计数）
+    pub evicted: u64,
+    pub stats: PeerStats,
+}
+
+/// 按对端统计的流量，最多保留 `limit` 个对端
+#[derive(Debug)]
+struct PeerTable {
+    limit: usize,
+    /// 对端的流量和最近一次出现的时刻（`clock` 的值）
+    peers: HashMap<SocketAddr, (PeerStats, u64)>,
+    other: OtherPeers,
+    clock: u64,
+}
+
+impl PeerTable {
+    fn new(limit: usize) -> Self {
+        Self { limit, peers: HashMap::new(), other: OtherPeers::default(), clock: 0 }
+    }
+
+    /// 取出对端的计数，新对端超出上限时先淘汰最久未出现的对端
+    fn entry(&mut self, peer: SocketAddr) -> &mut PeerStats {
+        self.clock += 1;
+        if !self.peers.contains_key(&peer) && self.peers.len() >= self.limit {
+            let oldest = self.peers.iter().min_by_key(|(_, (_, seen))| *seen).map(|(addr, _)| *addr);
+            if let Some((stats, _)) = oldest.and_then(|addr| self.peers.remove(&addr)) {
+                self.other.evicted += 1;
+                self.other.stats.add(&stats);
+            }
+        }
+        let (stats, seen) = self.peers.entry(peer).or_default();
+        *seen = self.clock;
+        stats
+    }
+}
+
+/// 流量统计（在发送端和接收线程之间共享）
+#[derive(Debug)]
+pub struct TrafficStats {
+    started_at: DateTime<Local>,
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+    decode_failures: AtomicU64,
+    write_errors: AtomicU64,
+    receive_errors: AtomicU64,
+    subscriber_drops: AtomicU64,
+    peers: Mutex<PeerTable>,
+}
+
+impl TrafficStats {
+    /// 创建新的统计，计时从现在开始
+    pub fn new() -> Self {
+        Self::with_peer_limit(MAX_TRACKED_PEERS)
+    }
+
+    /// 创建新的统计，最多单独统计 `max_peers` 个对端
+    pub fn with_peer_limit(max_peers: usize) -> Self {
+        Self {
+            started_at: Local::now(),
+            packets_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            decode_failures: AtomicU64::new(0),
+            write_errors: AtomicU64::new(0),
+            receive_errors: AtomicU64::new(0),
+            subscriber_drops: AtomicU64::new(0),
+            peers: Mutex::new(PeerTable::new(max_peers.max(1))),
+        }
+    }
+
+    /// 记录一次成功发送
+    pub fn record_sent(&self, peer: SocketAddr, bytes: usize) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        let mut peers = self.peers.lock().unwrap();
+        let entry = peers.entry(peer);
+        entry.packets_sent += 1;
+        entry.bytes_sent += bytes as u64;
+    }
+
+    /// 记录一次接收
+    pub fn record_received(&self, peer: SocketAddr, bytes: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        let mut peers = self.peers.lock().unwrap();
+        let entry = peers.entry(peer);
+        entry.packets_received += 1;
+        entry.bytes_received += bytes as u64;
+    }
+
+    /// 记录一次解码失败（非 UTF-8 数据）
+    pub fn record_decode_failure(&self) {
+        self.decode_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次消息文件写入错误
+    pub fn record_write_error(&self) {
+        self.write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录一次接收错误
+    pub fn record_receive_error(&self) {
+        self.receive_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 记录订阅者（WebSocket、webhook、exec）处理过慢而未能推送的消息数
+    pub fn record_subscriber_drops(&self, count: usize) {
+        self.subscriber_drops.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// 获取当前统计快照
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let peers = self.peers.lock().unwrap();
+        StatsSnapshot {
+            started_at: self.started_at,
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            decode_failures: self.decode_failures.load(Ordering::Relaxed),
+            write_errors: self.write_errors.load(Ordering::Relaxed),
+            receive_errors: self.receive_errors.load(Ordering::Relaxed),
+            subscriber_drops: self.subscriber_drops.load(Ordering::Relaxed),
+            peers: peers.peers.iter().map(|(addr, (stats, _))| (*addr, *stats)).collect(),
+            other_peers: peers.other,
+        }
+    }
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 流量统计快照
+#[derive(Debug, Clone)]
+pub struct StatsSnapshot {
+    pub started_at: DateTime<Local>,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub decode_failures: u64,
+    pub write_errors: u64,
+    pub receive_errors: u64,
+    /// 订阅者（WebSocket、webhook、exec）处理过慢而丢弃的消息数
+    pub subscriber_drops: u64,
+    pub peers: HashMap<SocketAddr, PeerStats>,
+    /// 超出对端数量上限后被淘汰的对端
+    pub other_peers: OtherPeers,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn evicts_least_recently_seen_peer_into_other() {
+        let stats = TrafficStats::with_peer_limit(2);
+        stats.record_received(addr(1), 10);
+        stats.record_received(addr(2), 20);
+        // 1 再次出现，2 成为最久未出现的对端
+        stats.record_sent(addr(1), 5);
+        stats.record_received(addr(3), 30);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.peers.len(), 2);
+        assert!(snapshot.peers.contains_key(&addr(1)));
+        assert!(snapshot.peers.contains_key(&addr(3)));
+        assert_eq!(snapshot.other_peers.evicted, 1);
+        assert_eq!(
+            snapshot.other_peers.stats,
+            PeerStats { packets_received: 1, bytes_received: 20, ..PeerStats::default() }
+        );
+
+        // 总计不受淘汰影响
+        assert_eq!(snapshot.packets_received, 3);
+        assert_eq!(snapshot.bytes_received, 60);
+        assert_eq!(snapshot.bytes_sent, 5);
+    }
+
+    #[test]
+    fn peer_count_stays_bounded() {
+        let stats = TrafficStats::with_peer_limit(8);
+        for port in 1..=1000 {
+            stats.record_received(addr(port), 1);
+        }
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.peers.len(), 8);
+        assert_eq!(snapshot.other_peers.evicted, 992);
+        let tracked: u64 = snapshot.peers.values().map(|p| p.packets_received).sum();
+        assert_eq!(tracked + snapshot.other_peers.stats.packets_received, 1000);
+        // 保留的是最近出现的对端
+        assert!((993..=1000).all(|port| snapshot.peers.contains_key(&addr(port))));
+    }
+}
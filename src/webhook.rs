@@ -0,0 +1,247 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::mpsc::{Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use anyhow::{Context, Result};
+
+use crate::message::ReceivedMessage;
+
+/// 默认队列长度
+pub const DEFAULT_WEBHOOK_QUEUE: usize = 100;
+/// 默认最大重试次数
+pub const DEFAULT_WEBHOOK_RETRIES: u32 = 3;
+/// 首次重试前的等待时间，之后每次翻倍
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// 单次请求超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 对端过滤条件
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerFilter {
+    /// 匹配该 IP 的任意端口
+    Ip(IpAddr),
+    /// 精确匹配 IP 和端口
+    Addr(SocketAddr),
+}
+
+impl PeerFilter {
+    /// 解析 `IP` 或 `IP:端口`
+    pub fn parse(s: &str) -> Result<Self> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(PeerFilter::Addr(addr));
+        }
+        s.parse::<IpAddr>()
+            .map(PeerFilter::Ip)
+            .with_context(|| format!("无效的对端地址: {}", s))
+    }
+
+    fn matches(&self, source: SocketAddr) -> bool {
+        match self {
+            PeerFilter::Ip(ip) => source.ip() == *ip,
+            PeerFilter::Addr(addr) => source == *addr,
+        }
+    }
+}
+
+/// Webhook 配置
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// 只转发来自这些对端的消息（为空时不限制）
+    pub peers: Vec<PeerFilter>,
+    /// 只转发包含任一关键字的消息（为空时不限制）
+    pub keywords: Vec<String>,
+    pub max_retries: u32,
+    pub queue_capacity: usize,
+}
+
+impl WebhookConfig {
+    /// 使用默认的重试次数和队列长度创建配置
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            peers: Vec::new(),
+            keywords: Vec::new(),
+            max_retries: DEFAULT_WEBHOOK_RETRIES,
+            queue_capacity: DEFAULT_WEBHOOK_QUEUE,
+        }
+    }
+
+    /// 消息是否满足过滤条件
+    pub fn matches(&self, message: &ReceivedMessage) -> bool {
+        let peer_ok = self.peers.is_empty() || self.peers.iter().any(|p| p.matches(message.source));
+        let keyword_ok = self.keywords.is_empty() || self.keywords.iter().any(|k| message.text.contains(k.as_str()));
+        peer_ok && keyword_ok
+    }
+}
+
+/// 在后台线程中运行 webhook 转发
+///
+/// `messages` 应来自 `UdpMessageHandler::subscribe`，其有界队列保证
+/// 缓慢的 webhook 端点只会导致消息丢弃，不会阻塞接收线程。
+pub fn spawn_webhook(
+    config: WebhookConfig,
+    messages: Receiver<ReceivedMessage>,
+    status_sender: Sender<String>,
+) -> Result<thread::JoinHandle<()>> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("创建 webhook 运行时失败")?;
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("创建 HTTP 客户端失败")?;
+
+    let handle = thread::spawn(move || {
+        let _ = status_sender.send(format!("Webhook 已启用: {}", config.url));
+        for message in messages {
+            if !config.matches(&message) {
+                continue;
+            }
+            if let Err(e) = runtime.block_on(deliver(&client, &config, &message)) {
+                let _ = status_sender.send(format!(
+                    "Webhook 投递失败 (来自 {} 的消息): {:#}",
+                    message.source, e
+                ));
+            }
+        }
+    });
+
+    Ok(handle)
+}
+
+/// 投递一条消息，失败时按指数退避重试
+async fn deliver(client: &reqwest::Client, config: &WebhookConfig, message: &ReceivedMessage) -> Result<()> {
+    let body = message.to_json();
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let result = client
+            .post(&config.url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match result {
+            Ok(_) => return Ok(()),
+            Err(_) if attempt < config.max_retries => {
+                attempt += 1;
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("重试 {} 次后仍然失败", attempt));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use chrono::Local;
+
+    fn message(source: &str, text: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            timestamp: Local::now(),
+            source: source.parse().unwrap(),
+            text: text.to_string(),
+            size: text.len(),
+            binary: false,
+        }
+    }
+
+    /// 本地 webhook 端点：第 n 个请求应答 `statuses[n]`（超出后沿用最后一个），返回请求计数
+    fn serve_statuses(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                assert!(String::from_utf8(body).unwrap().contains("\"message\""));
+
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[n.min(statuses.len() - 1)];
+                write!(stream, "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn matches_peer_filters() {
+        let mut config = WebhookConfig::new("http://127.0.0.1/");
+        assert!(config.matches(&message("10.0.0.1:9000", "hi")));
+
+        config.peers = vec![
+            PeerFilter::parse("10.0.0.1").unwrap(),
+            PeerFilter::parse("10.0.0.2:8080").unwrap(),
+        ];
+        assert!(config.matches(&message("10.0.0.1:9000", "hi")));
+        assert!(config.matches(&message("10.0.0.2:8080", "hi")));
+        assert!(!config.matches(&message("10.0.0.2:8081", "hi")));
+        assert!(!config.matches(&message("10.0.0.3:9000", "hi")));
+        assert!(PeerFilter::parse("not-an-ip").is_err());
+    }
+
+    #[test]
+    fn matches_keywords_together_with_peers() {
+        let mut config = WebhookConfig::new("http://127.0.0.1/");
+        config.keywords = vec!["alert".to_string(), "告警".to_string()];
+        assert!(config.matches(&message("10.0.0.1:9000", "disk alert")));
+        assert!(config.matches(&message("10.0.0.1:9000", "磁盘告警")));
+        assert!(!config.matches(&message("10.0.0.1:9000", "hello")));
+
+        // 对端和关键字需同时满足
+        config.peers = vec![PeerFilter::parse("10.0.0.1").unwrap()];
+        assert!(config.matches(&message("10.0.0.1:9000", "alert")));
+        assert!(!config.matches(&message("10.0.0.2:9000", "alert")));
+    }
+
+    #[tokio::test]
+    async fn retries_until_endpoint_succeeds() {
+        let (url, requests) = serve_statuses(vec![500, 503, 200]);
+        let config = WebhookConfig::new(&url);
+        let client = reqwest::Client::new();
+
+        deliver(&client, &config, &message("10.0.0.1:9000", "hi")).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let (url, requests) = serve_statuses(vec![500]);
+        let mut config = WebhookConfig::new(&url);
+        config.max_retries = 1;
+        let client = reqwest::Client::new();
+
+        let err = deliver(&client, &config, &message("10.0.0.1:9000", "hi")).await.unwrap_err();
+        assert!(err.to_string().contains("重试 1 次后仍然失败"));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::error::ProtocolError;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+use crate::http::token_matches;
+use crate::stats::TrafficStats;
+use crate::SharedHandler;
+
+/// 接收消息订阅队列长度
+const SUBSCRIBE_CAPACITY: usize = 256;
+/// 广播给 WebSocket 客户端的事件缓存长度
+const BROADCAST_CAPACITY: usize = 256;
+
+/// 连接 WebSocket 桥接时的校验设置
+#[derive(Debug, Clone)]
+pub struct WsAuth {
+    /// 连接地址中 `token` 参数必须等于该令牌
+    pub token: String,
+    /// 允许的网页来源（Origin 头），为空时只允许本机网页（localhost、127.0.0.1、[::1]）
+    pub allowed_origins: Vec<String>,
+}
+
+impl WsAuth {
+    /// 检查握手请求的令牌和来源，失败时返回拒绝原因
+    fn check(&self, request: &Request) -> Result<(), (StatusCode, &'static str)> {
+        let token = request
+            .uri()
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .unwrap_or_default();
+        if !token_matches(&self.token, token) {
+            return Err((StatusCode::UNAUTHORIZED, "缺少或错误的访问令牌"));
+        }
+        if let Some(origin) = request.headers().get("origin") {
+            let origin = origin.to_str().unwrap_or_default();
+            let allowed = if self.allowed_origins.is_empty() {
+                is_local_origin(origin)
+            } else {
+                self.allowed_origins.iter().any(|o| o == origin)
+            };
+            if !allowed {
+                return Err((StatusCode::FORBIDDEN, "不允许的来源"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Origin 是否为本机网页，例如 `http://localhost:3000`
+fn is_local_origin(origin: &str) -> bool {
+    let host = origin.split_once("://").map(|(_, rest)| rest).unwrap_or(origin);
+    let host = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split([':', '/']).next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+/// WebSocket 客户端发来的发送请求
+#[derive(Deserialize)]
+struct WsSendRequest {
+    target: String,
+    message: String,
+}
+
+/// 在后台线程中启动 WebSocket 桥接服务
+///
+/// 客户端需连接 `ws://地址/?token=<令牌>`；浏览器发来的 Origin 不在 `auth.allowed_origins` 中时拒绝握手，
+/// 防止其他网页跨站连接。
+///
+/// 协议（均为 JSON 文本帧）:
+/// - 客户端发送 `{"target": "IP:端口", "message": "..."}`，服务端通过 UDP 发出，
+///   向该客户端回复 `{"type": "ack", ...}` 或 `{"type": "error", ...}`，
+///   并向所有客户端广播 `{"type": "sent", ...}`
+/// - 接收器收到 UDP 报文时，向所有客户端推送 `{"type": "message", ...}`
+///
+/// 客户端处理过慢而跳过的事件计入 `stats` 的推送丢弃数。
+pub fn spawn_ws_server(
+    addr: SocketAddr,
+    auth: WsAuth,
+    handler: SharedHandler,
+    stats: Arc<TrafficStats>,
+    status_sender: Sender<String>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?;
+
+    let (events, _) = broadcast::channel::<String>(BROADCAST_CAPACITY);
+
+    // 把接收线程投递的消息转发到广播通道
+    let incoming = handler.lock().unwrap().subscribe(SUBSCRIBE_CAPACITY);
+    let forward = events.clone();
+    thread::spawn(move || {
+        for message in incoming {
+            let mut event = message.to_json();
+            event["type"] = json!("message");
+            // 没有客户端连接时发送失败，直接忽略
+            let _ = forward.send(event.to_string());
+        }
+    });
+
+    let handle = thread::spawn(move || {
+        runtime.block_on(async move {
+            let listener = match TcpListener::from_std(listener) {
+                Ok(l) => l,
+                Err(e) => {
+                    let _ = status_sender.send(format!("WebSocket 服务启动失败: {}", e));
+                    return;
+                }
+            };
+            let _ = status_sender.send(format!("WebSocket 服务已启动: ws://{}", addr));
+
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        let _ = status_sender.send(format!("WebSocket 接受连接失败: {}", e));
+                        continue;
+                    }
+                };
+                let handler = handler.clone();
+                let events = events.clone();
+                let status_sender = status_sender.clone();
+                let auth = auth.clone();
+                let stats = stats.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_client(stream, peer, &auth, handler, events, &stats).await {
+                        let _ = status_sender.send(format!("WebSocket 客户端 {} 错误: {}", peer, e));
+                    }
+                });
+            }
+        });
+    });
+
+    Ok(handle)
+}
+
+/// 处理一个 WebSocket 客户端
+async fn serve_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    auth: &WsAuth,
+    handler: SharedHandler,
+    events: broadcast::Sender<String>,
+    stats: &TrafficStats,
+) -> Result<(), WsError> {
+    // 错误类型由 tungstenite 的握手回调决定
+    #[allow(clippy::result_large_err)]
+    let check = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        auth.check(request).map(|_| response).map_err(|(status, reason)| {
+            let mut error = ErrorResponse::new(Some(reason.to_string()));
+            *error.status_mut() = status;
+            error
+        })
+    };
+    let ws = tokio_tungstenite::accept_hdr_async(stream, check).await?;
+    let (mut writer, mut reader) = ws.split();
+    let mut event_rx = events.subscribe();
+
+    loop {
+        tokio::select! {
+            frame = reader.next() => {
+                let text = match frame {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    // 浏览器直接关闭页面时常见，不视为错误
+                    Some(Err(WsError::ConnectionClosed))
+                    | Some(Err(WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake))) => break,
+                    Some(Err(e)) => return Err(e),
+                };
+                let reply = relay_to_udp(&handler, &events, peer, &text).await;
+                writer.send(Message::Text(reply.to_string())).await?;
+            }
+            event = next_event(&mut event_rx, stats) => {
+                match event {
+                    Some(event) => writer.send(Message::Text(event)).await?,
+                    None => break,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 等待下一个要推送给客户端的事件，广播通道关闭时返回 `None`
+///
+/// 客户端处理过慢时跳过丢失的事件，并把丢失数量计入推送丢弃。
+async fn next_event(event_rx: &mut broadcast::Receiver<String>, stats: &TrafficStats) -> Option<String> {
+    loop {
+        match event_rx.recv().await {
+            Ok(event) => return Some(event),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                stats.record_subscriber_drops(skipped as usize);
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// 把客户端请求通过 UDP 发出，返回给该客户端的应答
+async fn relay_to_udp(
+    handler: &SharedHandler,
+    events: &broadcast::Sender<String>,
+    peer: SocketAddr,
+    text: &str,
+) -> serde_json::Value {
+    let request: WsSendRequest = match serde_json::from_str(text) {
+        Ok(r) => r,
+        Err(e) => return json!({ "type": "error", "error": format!("无效的请求: {}", e) }),
+    };
+
+    let handler = handler.clone();
+    let target = request.target.clone();
+    let message = request.message.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        handler.lock().unwrap().send_message(&target, &message)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(size)) => {
+            let _ = events.send(json!({
+                "type": "sent",
+                "from": peer.to_string(),
+                "target": request.target,
+                "message": request.message,
+            }).to_string());
+            json!({ "type": "ack", "target": request.target, "bytes": size })
+        }
+        Ok(Err(e)) => json!({ "type": "error", "error": format!("发送失败: {}", e) }),
+        Err(e) => json!({ "type": "error", "error": format!("内部错误: {}", e) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_events_skipped_by_lagging_client() {
+        let stats = TrafficStats::new();
+        let (events, mut event_rx) = broadcast::channel::<String>(2);
+        for i in 0..5 {
+            events.send(format!("event {}", i)).unwrap();
+        }
+
+        // 通道只保留最新的 2 个事件，前 3 个已被覆盖
+        assert_eq!(next_event(&mut event_rx, &stats).await.as_deref(), Some("event 3"));
+        assert_eq!(stats.snapshot().subscriber_drops, 3);
+        assert_eq!(next_event(&mut event_rx, &stats).await.as_deref(), Some("event 4"));
+
+        drop(events);
+        assert_eq!(next_event(&mut event_rx, &stats).await, None);
+        assert_eq!(stats.snapshot().subscriber_drops, 3);
+    }
+}